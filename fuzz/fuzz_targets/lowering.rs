@@ -0,0 +1,28 @@
+#![no_main]
+
+use std::collections::HashMap;
+
+use libfuzzer_sys::fuzz_target;
+use nandu::Node;
+
+// Lowering to NAND must preserve the truth table of the
+// original expression for every variable assignment.
+fuzz_target!(|tree: Node| {
+    let mut lowered = tree.clone();
+    lowered.to_nand();
+
+    let vars: Vec<&str> = tree.variables().into_iter().collect();
+
+    for bits in 0..1u32 << vars.len() {
+        let env: HashMap<String, bool> = vars
+            .iter()
+            .enumerate()
+            .map(|(i, var)| (var.to_string(), bits & (1 << i) != 0))
+            .collect();
+        assert_eq!(
+            tree.eval(&env),
+            lowered.eval(&env),
+            "{tree} and {lowered} disagree"
+        );
+    }
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Any input must either translate or be rejected with an
+// error. Panics are reported as crashes by libFuzzer.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(input) = std::str::from_utf8(data) {
+        let _ = nandu::translate(input);
+    }
+});
@@ -0,0 +1,230 @@
+use std::fmt::Write;
+
+use crate::lex::Span;
+use crate::program::Def;
+use crate::tree::Node;
+
+// Where in the input the functions of a parsed tree came from.
+// The map has the same shape as the tree, so identical calls in
+// different places each have their own span. Calls to
+// user-defined functions and the functions in their bodies get
+// the span of the call, and the uses of a `let` binding get the
+// spans of the bound value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    span: Option<Span>,
+    args: Vec<SourceMap>,
+}
+
+impl SourceMap {
+    pub(crate) fn func(span: Span, args: Vec<SourceMap>) -> Self {
+        Self {
+            span: Some(span),
+            args,
+        }
+    }
+
+    // The map of a call to `def` at `span`, whose arguments
+    // have the maps in `args`.
+    pub(crate) fn expanded(def: &Def, span: Span, args: &[SourceMap]) -> Self {
+        fn visit(
+            node: &Node,
+            def: &Def,
+            span: Span,
+            args: &[SourceMap],
+        ) -> SourceMap {
+            match node {
+                Node::Func { args: inner, .. } => SourceMap::func(
+                    span,
+                    inner
+                        .iter()
+                        .map(|arg| visit(arg, def, span, args))
+                        .collect(),
+                ),
+                Node::Var { id } => {
+                    match def.params.iter().position(|p| p == id) {
+                        Some(i) => args[i].clone(),
+                        None => SourceMap::default(),
+                    }
+                },
+                Node::Const { .. } => SourceMap::default(),
+            }
+        }
+
+        visit(&def.body, def, span, args)
+    }
+
+    // Span of the call which built the function at `path` in the
+    // tree, if it was parsed. Each number in `path` is the index
+    // of the argument to go to next, like in `DiffEntry`.
+    pub fn span(&self, path: &[usize]) -> Option<Span> {
+        match path.split_first() {
+            Some((&i, path)) => self.args.get(i)?.span(path),
+            None => self.span,
+        }
+    }
+
+    // Add `arg` as the last argument of the function.
+    pub(crate) fn push_arg(&mut self, arg: SourceMap) {
+        self.args.push(arg);
+    }
+
+    pub(crate) fn set_span(&mut self, span: Span) {
+        self.span = Some(span);
+    }
+}
+
+impl Node {
+    // Lower the expression like `to_nand` and print it with a
+    // `/* start..end */` comment after every `Nand`. The comment
+    // gives the byte range of the call in the input which the
+    // `Nand` was lowered from.
+    pub fn to_nand_annotated(&self, map: &SourceMap) -> String {
+        let mut out = String::new();
+        annotate(self, map, &mut out);
+        out
+    }
+}
+
+// `map` is the map of `node` itself.
+fn annotate(node: &Node, map: &SourceMap, out: &mut String) {
+    let Node::Func { id, args } = node else {
+        write!(out, "{node}").unwrap();
+        return;
+    };
+    let default = SourceMap::default();
+    let lowered_args: Vec<String> = args
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| {
+            let mut out = String::new();
+            annotate(arg, map.args.get(i).unwrap_or(&default), &mut out);
+            out
+        })
+        .collect();
+    // Lower the function on its own, with placeholders for the
+    // arguments.
+    let mut template = Node::Func {
+        id:   *id,
+        args: Node::placeholders(args.len()),
+    };
+    template.to_nand();
+    render(&template, &lowered_args, map.span, out);
+}
+
+fn render(
+    template: &Node,
+    args: &[String],
+    span: Option<Span>,
+    out: &mut String,
+) {
+    match template {
+        Node::Func { id, args: inputs } => {
+            write!(out, "{id}(").unwrap();
+            for (i, input) in inputs.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                render(input, args, span, out);
+            }
+            out.push(')');
+            if let Some(span) = span {
+                write!(out, " /* {}..{} */", span.start, span.end).unwrap();
+            }
+        },
+        Node::Var { id } => match Node::placeholder_index(id) {
+            Some(i) => out.push_str(&args[i]),
+            None => out.push_str(id),
+        },
+        Node::Const { .. } => write!(out, "{template}").unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lex::{lex, Span};
+    use crate::parse::start_multi_mapped;
+
+    fn annotated(input: &str) -> String {
+        let (trees, maps) =
+            start_multi_mapped(&mut lex(input).peekable()).unwrap();
+        trees[0].to_nand_annotated(&maps[0])
+    }
+
+    #[test]
+    fn annotation_of_and_references_the_call() {
+        assert_eq!(
+            annotated("And(a, b)"),
+            "Nand(Nand(a, b) /* 0..9 */, Nand(a, b) /* 0..9 */) /* 0..9 */"
+        );
+    }
+
+    #[test]
+    fn annotations_of_nested_calls_and_operators() {
+        assert_eq!(
+            annotated("Or(a, ~b)"),
+            "Nand(Nand(a, a) /* 0..9 */, \
+             Nand(Nand(b, b) /* 6..8 */, Nand(b, b) /* 6..8 */) /* 0..9 */) \
+             /* 0..9 */"
+        );
+        assert_eq!(
+            annotated("x & y"),
+            "Nand(Nand(x, y) /* 0..5 */, Nand(x, y) /* 0..5 */) /* 0..5 */"
+        );
+    }
+
+    #[test]
+    fn repeated_calls_have_their_own_spans() {
+        // The second `Not(a)` is at 18..24, not where the first one is.
+        let or = annotated("Or(Not(a), Xor(b, Not(a)))");
+        assert!(or.starts_with("Nand(Nand(Nand(a, a) /* 3..9 */"));
+        assert_eq!(or.matches("Nand(a, a) /* 3..9 */").count(), 2);
+        assert_eq!(or.matches("Nand(a, a) /* 18..24 */").count(), 6);
+        let input = "Not(a)\nNot(a)";
+        let (trees, maps) =
+            start_multi_mapped(&mut lex(input).peekable()).unwrap();
+        assert_eq!(maps[0].span(&[]), Some(Span { start: 0, end: 6 }));
+        assert_eq!(
+            maps[1].span(&[]),
+            Some(Span {
+                start: 7,
+                end:   13,
+            })
+        );
+        assert_eq!(
+            trees[1].to_nand_annotated(&maps[1]),
+            "Nand(a, a) /* 7..13 */"
+        );
+    }
+
+    #[test]
+    fn spans_of_bindings_and_definitions() {
+        let input =
+            "def Inv(x) = Not(x)\nlet n = Not(b) in And(Inv(Not(a)), n)";
+        let (_, maps) = start_multi_mapped(&mut lex(input).peekable()).unwrap();
+        let span = |start, end| Some(Span { start, end });
+        assert_eq!(maps[0].span(&[]), span(38, 57));
+        assert_eq!(maps[0].span(&[0]), span(42, 53));
+        assert_eq!(maps[0].span(&[0, 0]), span(46, 52));
+        assert_eq!(maps[0].span(&[1]), span(28, 34));
+        assert_eq!(maps[0].span(&[1, 0]), None);
+        assert_eq!(maps[0].span(&[2]), None);
+    }
+
+    #[test]
+    fn annotation_without_comments_is_the_translation() {
+        let input = "def Diff(a, b) = Xor(a, b)\nDiff(1, Imp(x, And(x, y, z)))";
+        let (trees, maps) =
+            start_multi_mapped(&mut lex(input).peekable()).unwrap();
+        let mut tree = trees[0].clone();
+        let with_comments = tree.to_nand_annotated(&maps[0]);
+        tree.to_nand();
+        // Drop every ` /* .. */` again.
+        let mut without = with_comments.clone();
+        while let Some(start) = without.find(" /*") {
+            let end = without[start..].find("*/").unwrap() + start + 2;
+            without.replace_range(start..end, "");
+        }
+        assert_eq!(without, tree.to_string());
+    }
+}
@@ -0,0 +1,110 @@
+use std::fmt::Write;
+
+use crate::names::output_names;
+use crate::netlist::Signal;
+use crate::tree::Node;
+
+impl Node {
+    // Emit a BLIF model which computes the expression using one
+    // two-input `Nand` table per gate. The variables become
+    // inputs in the order they first appear and there is a
+    // single output. The expression is lowered first if it
+    // isn't already. Variables whose names have whitespace or
+    // other characters which mean something in BLIF are renamed.
+    pub fn to_blif(&self, model_name: &str) -> String {
+        let netlist = self.to_netlist();
+        let vars: Vec<&str> =
+            netlist.inputs().iter().map(String::as_str).collect();
+        // Gates and constants start with an underscore, so they
+        // can't be confused with variables, but the output doesn't.
+        let names = output_names(&vars, |id| {
+            id.chars()
+                .all(|c| c.is_ascii_graphic() && !matches!(c, '#' | '\\' | '='))
+                && !id.starts_with(['_', '.'])
+        });
+        let inputs: Vec<&str> =
+            vars.iter().map(|id| names[id].as_str()).collect();
+        let mut output = "out".to_owned();
+        while inputs.contains(&output.as_str()) {
+            output.push('_');
+        }
+        let name = |signal: &Signal| match signal {
+            Signal::Gate(id) => format!("_g{}", id.index()),
+            Signal::Input(id) => names[id.as_str()].clone(),
+            Signal::Const(value) => format!("_c{}", u8::from(*value)),
+        };
+
+        let mut out = String::new();
+        writeln!(out, ".model {model_name}").unwrap();
+        if !inputs.is_empty() {
+            writeln!(out, ".inputs {}", inputs.join(" ")).unwrap();
+        }
+        writeln!(out, ".outputs {output}").unwrap();
+        // Constants are tables without inputs. The one for `1` has
+        // a single row, while the one for `0` has none.
+        let uses_const = |value| {
+            netlist.output() == &Signal::Const(value)
+                || netlist
+                    .gates()
+                    .iter()
+                    .any(|gate| gate.inputs.contains(&Signal::Const(value)))
+        };
+        if uses_const(false) {
+            writeln!(out, ".names _c0").unwrap();
+        }
+        if uses_const(true) {
+            writeln!(out, ".names _c1\n1").unwrap();
+        }
+        for (i, gate) in netlist.gates().iter().enumerate() {
+            let [a, b] = &gate.inputs;
+            writeln!(out, ".names {} {} _g{i}", name(a), name(b)).unwrap();
+            // The output of a `Nand` is `1` if either input is `0`.
+            writeln!(out, "0- 1\n-0 1").unwrap();
+        }
+        writeln!(out, ".names {} {output}\n1 1", name(netlist.output()))
+            .unwrap();
+        out.push_str(".end");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lex::lex;
+    use crate::parse::start;
+
+    #[test]
+    fn blif_of_single_nand() {
+        let tree = start(&mut lex("Nand(a, b)").peekable()).unwrap();
+        assert_eq!(
+            tree.to_blif("top"),
+            ".model top\n\
+             .inputs a b\n\
+             .outputs out\n\
+             .names a b _g0\n\
+             0- 1\n\
+             -0 1\n\
+             .names _g0 out\n\
+             1 1\n\
+             .end"
+        );
+    }
+
+    #[test]
+    fn blif_defines_constants_and_a_unique_output() {
+        let tree = start(&mut lex("Nand(out, 1)").peekable()).unwrap();
+        let blif = tree.to_blif("top");
+        assert!(blif.contains(".inputs out\n.outputs out_\n"));
+        assert!(blif.contains(".names _c1\n1\n.names out _c1 _g0\n"));
+        assert!(!blif.contains("_c0"));
+        assert!(blif.ends_with(".names _g0 out_\n1 1\n.end"));
+    }
+
+    #[test]
+    fn blif_renames_quoted_variables() {
+        let input = "Nand(`my var`, `#a`)";
+        let blif = start(&mut lex(input).peekable()).unwrap().to_blif("top");
+        assert!(blif.contains(".inputs my_var v_a\n"));
+        assert!(blif.contains(".names my_var v_a _g0\n"));
+    }
+}
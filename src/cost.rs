@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use crate::parse::Id;
+use crate::tree::Node;
+
+// Cost of each kind of gate in some technology, like its area
+// or its delay. Gates without a cost of their own cost 1.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CostModel {
+    costs: HashMap<Id, u64>,
+}
+
+impl CostModel {
+    // Same model, but each `id` gate costs `cost`.
+    pub fn with(mut self, id: Id, cost: u64) -> Self {
+        self.costs.insert(id, cost);
+        self
+    }
+
+    pub fn cost(&self, id: Id) -> u64 {
+        self.costs.get(&id).copied().unwrap_or(1)
+    }
+}
+
+impl Node {
+    // Sum of the costs of all functions in the tree. Variables
+    // and constants are free.
+    pub fn cost(&self, model: &CostModel) -> u64 {
+        match self {
+            Node::Func { id, args } => {
+                let args: u64 = args.iter().map(|arg| arg.cost(model)).sum();
+                model.cost(*id) + args
+            },
+            Node::Var { .. } | Node::Const { .. } => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::lex;
+    use crate::parse::start;
+
+    fn parse(input: &str) -> Node {
+        start(&mut lex(input).peekable()).unwrap()
+    }
+
+    #[test]
+    fn default_model_counts_functions() {
+        let model = CostModel::default();
+        let mut tree = parse("Or(And(a, b), Not(c), Xor(a, c))");
+        assert_eq!(tree.cost(&model), 4);
+        tree.to_nand();
+        assert_eq!(tree.cost(&model), tree.count_gates() as u64);
+        assert_eq!(parse("a").cost(&model), 0);
+    }
+
+    #[test]
+    fn custom_model_weights_each_gate() {
+        let model = CostModel::default()
+            .with(Id::Nand, 4)
+            .with(Id::Not, 2)
+            .with(Id::And, 6)
+            .with(Id::Or, 6);
+        let mut tree = parse("Or(And(a, b), Not(c))");
+        assert_eq!(tree.cost(&model), 6 + 6 + 2);
+        // The `Or` uses both of its arguments twice, so there are
+        // 1 + 2 + 2 * 3 + 2 * 1 `Nand`s after lowering.
+        tree.to_nand();
+        assert_eq!(tree.count_gates(), 11);
+        assert_eq!(tree.cost(&model), 4 * 11);
+    }
+}
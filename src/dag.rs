@@ -0,0 +1,564 @@
+use std::collections::HashMap;
+
+use crate::parse::Id;
+use crate::tree::Node;
+
+// Position of a node in a `Dag`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+// Same as `Node`, but the arguments of a function refer to
+// other nodes in the same `Dag` instead of owning them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DagNode {
+    Func { id: Id, args: Vec<NodeId> },
+    Var { id: String },
+    Const { value: bool },
+}
+
+// A tree in which structurally identical sub-trees are stored
+// only once. Nodes only ever refer to nodes which were added
+// before them, so the arguments of a node always have a lower
+// index than the node itself.
+#[derive(Debug, Clone)]
+pub struct Dag {
+    nodes: Vec<DagNode>,
+    cache: HashMap<DagNode, NodeId>,
+    root:  NodeId,
+}
+
+impl Dag {
+    // Add a node unless an identical one exists already.
+    // Either way the id of the node in the DAG is returned.
+    fn insert(&mut self, node: DagNode) -> NodeId {
+        if let Some(&id) = self.cache.get(&node) {
+            return id;
+        }
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(node.clone());
+        self.cache.insert(node, id);
+        id
+    }
+
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    pub fn node(&self, id: NodeId) -> &DagNode {
+        &self.nodes[id.0]
+    }
+
+    // All nodes in the order they were added. Arguments
+    // come before the functions which use them.
+    pub fn nodes(&self) -> &[DagNode] {
+        &self.nodes
+    }
+
+    // Number of nodes after sharing. Every distinct sub-tree
+    // is counted once, no matter how often it is used.
+    pub fn distinct_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // Number of distinct `Nand` functions. A shared gate
+    // is counted once, no matter how often it is used.
+    pub fn count_gates(&self) -> usize {
+        self.nodes
+            .iter()
+            .filter(|node| matches!(node, DagNode::Func { id: Id::Nand, .. }))
+            .count()
+    }
+
+    // Expand the DAG back into a tree. This duplicates all
+    // shared nodes again.
+    pub fn to_tree(&self) -> Node {
+        self.tree_at(self.root)
+    }
+
+    // Check that walking the DAG from its root ends. Every
+    // `Dag` built by `share` is valid, since arguments always
+    // come before the functions using them, but code which
+    // builds or rewrites the nodes by hand could produce a node
+    // which is its own argument, or an argument which doesn't
+    // exist. Printing or evaluating such a DAG would never end
+    // or panic.
+    pub fn validate(&self) -> Result<(), StructureError> {
+        // Nodes still being visited and nodes known to be fine.
+        let mut open = vec![false; self.nodes.len()];
+        let mut done = vec![false; self.nodes.len()];
+        if self.root.0 >= self.nodes.len() {
+            return Err(StructureError::MissingNode(self.root));
+        }
+        // Depth-first search without recursion. The second part
+        // of each entry says how many arguments were visited.
+        let mut stack = vec![(self.root, 0)];
+        open[self.root.0] = true;
+        while let Some((id, next)) = stack.pop() {
+            let args = match &self.nodes[id.0] {
+                DagNode::Func { args, .. } => args.as_slice(),
+                _ => &[],
+            };
+            let Some(&arg) = args.get(next) else {
+                open[id.0] = false;
+                done[id.0] = true;
+                continue;
+            };
+            stack.push((id, next + 1));
+            if arg.0 >= self.nodes.len() {
+                return Err(StructureError::MissingNode(arg));
+            }
+            if open[arg.0] {
+                return Err(StructureError::Cycle(arg));
+            }
+            if !done[arg.0] {
+                open[arg.0] = true;
+                stack.push((arg, 0));
+            }
+        }
+        Ok(())
+    }
+
+    // Insert the nodes of a lowering `template` from
+    // `Node::lower_func`. Its placeholders are the nodes in `args`.
+    fn insert_template(&mut self, template: &Node, args: &[NodeId]) -> NodeId {
+        let node = match template {
+            Node::Func { id, args: inputs } => DagNode::Func {
+                id:   *id,
+                args: inputs
+                    .iter()
+                    .map(|input| self.insert_template(input, args))
+                    .collect(),
+            },
+            Node::Var { id } => match Node::placeholder_index(id) {
+                Some(i) => return args[i],
+                None => DagNode::Var { id: id.clone() },
+            },
+            Node::Const { value } => DagNode::Const { value: *value },
+        };
+        self.insert(node)
+    }
+
+    fn tree_at(&self, id: NodeId) -> Node {
+        match self.node(id) {
+            DagNode::Func { id, args } => Node::Func {
+                id:   *id,
+                args: args.iter().map(|arg| self.tree_at(*arg)).collect(),
+            },
+            DagNode::Var { id } => Node::Var { id: id.clone() },
+            DagNode::Const { value } => Node::Const { value: *value },
+        }
+    }
+
+    // The expression of the node `id`, with the names in `names`
+    // in place of the nodes which are bound already.
+    fn expr_at(&self, id: NodeId, names: &[Option<String>]) -> String {
+        if let Some(name) = &names[id.0] {
+            return name.clone();
+        }
+        match self.node(id) {
+            DagNode::Func { id, args } => {
+                let args: Vec<String> =
+                    args.iter().map(|arg| self.expr_at(*arg, names)).collect();
+                format!("{id}({})", args.join(", "))
+            },
+            _ => self.tree_at(id).to_string(),
+        }
+    }
+}
+
+// Print the DAG as an expression in which every function that's
+// used more than once is bound by a `let`, like
+// `let g0 = Nand(a, b) in Nand(g0, g0)`. Parsing it again gives
+// the same tree as `to_tree`.
+impl std::fmt::Display for Dag {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // How often each node which is reachable from the root is
+        // used. Arguments come before the functions using them, so
+        // every user of a node is counted before the node itself.
+        let mut uses = vec![0; self.nodes.len()];
+        uses[self.root.0] = 1;
+        for (i, node) in self.nodes.iter().enumerate().rev() {
+            let DagNode::Func { args, .. } = node else {
+                continue;
+            };
+            if uses[i] > 0 {
+                for arg in args {
+                    uses[arg.0] += 1;
+                }
+            }
+        }
+
+        // The names of the bindings mustn't be those of variables.
+        let mut prefix = "g".to_owned();
+        let is_taken = |prefix: &str| {
+            self.nodes.iter().any(|node| match node {
+                DagNode::Var { id } => {
+                    id.strip_prefix(prefix).is_some_and(|rest| {
+                        rest.chars().all(|c| c.is_ascii_digit())
+                    })
+                },
+                _ => false,
+            })
+        };
+        while is_taken(&prefix) {
+            prefix.push('_');
+        }
+
+        let mut names: Vec<Option<String>> = vec![None; self.nodes.len()];
+        let mut bound = 0;
+        for (i, node) in self.nodes.iter().enumerate() {
+            if uses[i] > 1 && matches!(node, DagNode::Func { .. }) {
+                let name = format!("{prefix}{bound}");
+                let expr = self.expr_at(NodeId(i), &names);
+                write!(f, "let {name} = {expr} in ")?;
+                names[i] = Some(name);
+                bound += 1;
+            }
+        }
+        // Nothing uses the root, so it's never bound.
+        write!(f, "{}", self.expr_at(self.root, &names))
+    }
+}
+
+// Why a shared structure can't be walked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StructureError {
+    // The node can be reached from itself.
+    Cycle(NodeId),
+    // A node refers to a node which doesn't exist.
+    MissingNode(NodeId),
+}
+
+impl std::fmt::Display for StructureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Cycle(id) => {
+                write!(f, "node {} is its own argument", id.index())
+            },
+            Self::MissingNode(id) => {
+                write!(f, "node {} doesn't exist", id.index())
+            },
+        }
+    }
+}
+
+impl std::error::Error for StructureError {}
+
+impl Node {
+    // A tree owns its arguments, so it can't contain a cycle and
+    // is always valid. This is the counterpart of `Dag::validate`
+    // for code which handles both.
+    pub fn validate(&self) -> Result<(), StructureError> {
+        Ok(())
+    }
+
+    // Hash-cons the tree into a DAG. This is meant to run after
+    // `to_nand`, which duplicates lots of sub-trees.
+    pub fn share(&self) -> Dag {
+        self.share_counted().0
+    }
+
+    // Same as `share`, but also returns the number of sub-trees
+    // which were duplicates of one stored already. All sub-trees
+    // are compared, no matter where in the tree they are.
+    pub fn share_counted(&self) -> (Dag, usize) {
+        fn visit(node: &Node, dag: &mut Dag, visited: &mut usize) -> NodeId {
+            *visited += 1;
+            let node = match node {
+                Node::Func { id, args } => DagNode::Func {
+                    id:   *id,
+                    args: args
+                        .iter()
+                        .map(|arg| visit(arg, dag, visited))
+                        .collect(),
+                },
+                Node::Var { id } => DagNode::Var { id: id.clone() },
+                Node::Const { value } => DagNode::Const { value: *value },
+            };
+            dag.insert(node)
+        }
+
+        let mut dag = Dag {
+            nodes: vec![],
+            cache: HashMap::new(),
+            root:  NodeId(0),
+        };
+        let mut visited = 0;
+        dag.root = visit(self, &mut dag, &mut visited);
+        let duplicates = visited - dag.distinct_nodes();
+        (dag, duplicates)
+    }
+
+    // Lower the tree straight into a DAG. This is the same as
+    // `to_nand` followed by `share`, but the lowered tree is never
+    // built. That tree doubles in size with every nested `And`,
+    // so for wide `And`s and `Or`s it's much too big.
+    pub fn to_nand_dag(&self) -> Dag {
+        fn visit(node: &Node, dag: &mut Dag) -> NodeId {
+            let (id, args) = match node {
+                Node::Func { id, args } => (*id, args),
+                Node::Var { id } => {
+                    return dag.insert(DagNode::Var { id: id.clone() })
+                },
+                Node::Const { value } => {
+                    return dag.insert(DagNode::Const { value: *value })
+                },
+            };
+            let args: Vec<NodeId> =
+                args.iter().map(|arg| visit(arg, dag)).collect();
+            match id {
+                // Fold to the left, like `to_nand` does.
+                Id::And | Id::Or if args.len() > 2 => {
+                    let template = Node::lower_func(id, Node::placeholders(2));
+                    args[1..].iter().fold(args[0], |acc, &arg| {
+                        dag.insert_template(&template, &[acc, arg])
+                    })
+                },
+                _ => {
+                    let template =
+                        Node::lower_func(id, Node::placeholders(args.len()));
+                    dag.insert_template(&template, &args)
+                },
+            }
+        }
+
+        let mut dag = Dag {
+            nodes: vec![],
+            cache: HashMap::new(),
+            root:  NodeId(0),
+        };
+        dag.root = visit(self, &mut dag);
+        dag
+    }
+
+    // Build the circuit with as few gates as the available
+    // passes allow: constants are folded, the tree is lowered
+    // and simplified, and identical sub-trees become a single
+    // gate. The tree itself is left untouched.
+    pub fn minimize(&self) -> Dag {
+        let mut tree = self.clone();
+        tree.fold();
+        tree.to_nand();
+        tree.simplify();
+        tree.share()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::assignments;
+    use crate::lex::lex;
+    use crate::parse::start;
+
+    fn lowered(input: &str) -> Node {
+        let mut tree = start(&mut lex(input).peekable()).unwrap();
+        tree.to_nand();
+        tree
+    }
+
+    #[test]
+    fn share_stores_identical_subtrees_once() {
+        let dag = lowered("And(a, b)").share();
+        // `a`, `b`, `Nand(a, b)` and the outer `Nand`.
+        assert_eq!(dag.distinct_nodes(), 4);
+        let DagNode::Func { args, .. } = dag.node(dag.root()) else {
+            panic!("root of the DAG is not a function");
+        };
+        assert_eq!(args[0], args[1]);
+    }
+
+    #[test]
+    fn share_counted_finds_distant_duplicates() {
+        let input = "Or(And(x, y), Not(Xor(And(x, y), z)))";
+        let tree = lowered(input);
+        let (dag, duplicates) = tree.share_counted();
+        fn tree_nodes(node: &Node) -> usize {
+            match node {
+                Node::Func { args, .. } => {
+                    1 + args.iter().map(tree_nodes).sum::<usize>()
+                },
+                Node::Var { .. } | Node::Const { .. } => 1,
+            }
+        }
+        // The lowered `And(x, y)` is five nodes, and its second
+        // use is one of the duplicates.
+        assert!(duplicates >= 5);
+        assert_eq!(dag.distinct_nodes() + duplicates, tree_nodes(&tree));
+        assert!(dag.distinct_nodes() < tree_nodes(&tree));
+        assert_eq!(dag.to_tree(), tree);
+    }
+
+    #[test]
+    fn display_binds_shared_gates() {
+        let dag = lowered("And(a, b)").minimize();
+        assert_eq!(dag.to_string(), "let g0 = Nand(a, b) in Nand(g0, g0)");
+        let dag = lowered("Not(a)").share();
+        assert_eq!(dag.to_string(), "Nand(a, a)");
+    }
+
+    #[test]
+    fn display_of_dag_parses_as_its_tree() {
+        for input in [
+            "Xor(And(a, b), Or(b, Not(c)))",
+            "And(g0, Or(g0, g))",
+            "Nand(1, And(a, 0))",
+            "x",
+        ] {
+            let dag = lowered(input).share();
+            let printed = dag.to_string();
+            let parsed = start(&mut lex(&printed).peekable()).unwrap();
+            assert_eq!(parsed, dag.to_tree(), "{printed}");
+        }
+        let dag = lowered("And(g0, g1)").share();
+        assert!(dag.to_string().starts_with("let g_0 = Nand(g0, g1) in"));
+    }
+
+    #[test]
+    fn count_gates_counts_shared_gates_once() {
+        let tree = lowered("And(a, Or(b, c))");
+        assert_eq!(tree.count_gates(), 9);
+        // `Nand(b, b)`, `Nand(c, c)`, the `Or`'s outer `Nand`,
+        // `Nand(a, or)` and the `And`'s outer `Nand`.
+        assert_eq!(tree.share().count_gates(), 5);
+    }
+
+    #[test]
+    fn share_keeps_the_structure() {
+        let tree = lowered("Xor(And(a, b), Or(b, Not(c)))");
+        assert_eq!(tree.share().to_tree(), tree);
+    }
+
+    #[test]
+    fn arguments_come_before_their_functions() {
+        let dag = lowered("Or(Xor(a, b), And(c, a))").share();
+        for (i, node) in dag.nodes().iter().enumerate() {
+            if let DagNode::Func { args, .. } = node {
+                assert!(args.iter().all(|arg| arg.index() < i));
+            }
+        }
+        assert_eq!(dag.root().index(), dag.distinct_nodes() - 1);
+    }
+
+    #[test]
+    fn minimize_builds_identical_gates_once() {
+        let tree = lowered("And(a, b)");
+        assert_eq!(tree.count_gates(), 3);
+        let dag = tree.minimize();
+        assert_eq!(dag.count_gates(), 2);
+        for env in assignments(&["a", "b"]) {
+            assert_eq!(dag.to_tree().eval(&env), tree.eval(&env));
+        }
+    }
+
+    #[test]
+    fn minimize_keeps_the_truth_table() {
+        let input = "Or(Xor(a, And(b, 1)), Not(Not(c)), Imp(c, a))";
+        let tree = start(&mut lex(input).peekable()).unwrap();
+        let dag = tree.minimize();
+        assert!(dag.count_gates() < lowered(input).count_gates());
+        for env in assignments(&["a", "b", "c"]) {
+            assert_eq!(dag.to_tree().eval(&env), tree.eval(&env));
+        }
+    }
+
+    #[test]
+    fn to_nand_dag_is_the_shared_lowered_tree() {
+        let inputs = [
+            "a",
+            "Xor(And(a, b), Or(b, Not(c)))",
+            "Nand(Nor(a, 1), Xnor(b, Imp(a, c)))",
+        ];
+        for input in inputs {
+            let tree = start(&mut lex(input).peekable()).unwrap();
+            assert_eq!(tree.to_nand_dag().to_tree(), lowered(input));
+        }
+    }
+
+    #[test]
+    fn to_nand_dag_of_wide_functions() {
+        for width in 2..8 {
+            let vars: Vec<String> =
+                (0..width).map(|i| format!("x{i}")).collect();
+            let vars = vars.join(", ");
+            for input in [format!("And({vars})"), format!("Or({vars})")] {
+                let tree = start(&mut lex(&input).peekable()).unwrap();
+                let dag = tree.to_nand_dag();
+                assert_eq!(dag.to_tree(), lowered(&input));
+                assert_eq!(
+                    dag.count_gates(),
+                    lowered(&input).share().count_gates()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn shared_dags_are_valid() {
+        let tree = lowered("Or(Xor(a, b), And(c, a))");
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.share().validate(), Ok(()));
+    }
+
+    fn dag(nodes: Vec<DagNode>, root: usize) -> Dag {
+        Dag {
+            nodes,
+            cache: HashMap::new(),
+            root: NodeId(root),
+        }
+    }
+
+    fn nand(a: usize, b: usize) -> DagNode {
+        DagNode::Func {
+            id:   Id::Nand,
+            args: vec![NodeId(a), NodeId(b)],
+        }
+    }
+
+    #[test]
+    fn validate_reports_cycles() {
+        let a = DagNode::Var { id: "a".to_owned() };
+        // The root uses node 1, which uses the root again.
+        let cyclic = dag(vec![nand(1, 2), nand(2, 0), a.clone()], 0);
+        assert_eq!(cyclic.validate(), Err(StructureError::Cycle(NodeId(0))));
+        let own_arg = dag(vec![a.clone(), nand(0, 1)], 1);
+        assert_eq!(own_arg.validate(), Err(StructureError::Cycle(NodeId(1))));
+        // Using a node twice is fine, as long as it's not its own
+        // argument.
+        let diamond = dag(vec![a, nand(0, 0), nand(1, 1)], 2);
+        assert_eq!(diamond.validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_reports_missing_nodes() {
+        let dangling = dag(vec![DagNode::Const { value: true }, nand(0, 5)], 1);
+        assert_eq!(
+            dangling.validate(),
+            Err(StructureError::MissingNode(NodeId(5)))
+        );
+        assert_eq!(
+            dag(vec![], 0).validate(),
+            Err(StructureError::MissingNode(NodeId(0)))
+        );
+    }
+
+    #[test]
+    fn nested_ands_grow_linearly_when_shared() {
+        let mut input = "a".to_owned();
+        for _ in 0..10 {
+            input = format!("And({input}, b)");
+        }
+        let tree = lowered(&input);
+        let stats = tree.stats();
+        // Each `And` adds two `Nand`s to the DAG, but doubles the
+        // number of gates in the tree.
+        assert_eq!(stats.gate_count, 3 * ((1 << 10) - 1));
+        assert_eq!(tree.share().distinct_nodes(), 2 + 2 * 10);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::lex::Span;
+
+// Convert a byte `offset` into `input` to a line and column,
+// both starting at one. Columns count characters, not bytes,
+// so they match what an editor displays. Lines end with `\n`,
+// `\r\n` or a single `\r`.
+pub fn line_col(input: &str, offset: usize) -> (usize, usize) {
+    let mut end = offset.min(input.len());
+    while !input.is_char_boundary(end) {
+        end -= 1;
+    }
+    let bytes = input.as_bytes();
+    let mut line = 1;
+    let mut line_start = 0;
+    for (i, byte) in bytes[..end].iter().enumerate() {
+        let is_break = match byte {
+            b'\n' => true,
+            // The `\n` of a `\r\n` ends the line.
+            b'\r' => bytes.get(i + 1) != Some(&b'\n'),
+            _ => false,
+        };
+        if is_break {
+            line += 1;
+            line_start = i + 1;
+        }
+    }
+    let col = input[line_start..end].chars().count() + 1;
+    (line, col)
+}
+
+// The line of `input` which `span` starts on, and below it a
+// line with a `^` under the first character of `span` and `~`
+// under the rest of it on that line, both ending with `\n`.
+// Spans which are empty, or at the end of the input, get a
+// single `^` where they start. Tabs before the `^` are kept, so
+// that it lines up however wide a tab is shown.
+pub fn caret_lines(input: &str, span: Span) -> String {
+    let is_break = |c: char| c == '\n' || c == '\r';
+    let (_, col) = line_col(input, span.start);
+    let start = span.start.min(input.len());
+    let line_start = input[..start].rfind(is_break).map_or(0, |i| i + 1);
+    let line_end = input[line_start..]
+        .find(is_break)
+        .map_or(input.len(), |i| line_start + i);
+    let line = &input[line_start..line_end];
+    let end = span.end.clamp(start, line_end);
+    let width = input.get(start..end).map_or(0, |s| s.chars().count());
+    let padding: String = line
+        .chars()
+        .take(col - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    format!(
+        "{line}\n{padding}^{}\n",
+        "~".repeat(width.saturating_sub(1))
+    )
+}
+
+// Number of characters which must be inserted, removed or
+// replaced to turn `a` into `b` (the Levenshtein distance).
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // Distances from the part of `a` seen so far to each
+    // prefix of `b`.
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(a_char != *b_char);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_col_on_first_line() {
+        assert_eq!(line_col("Nand(a, b))", 0), (1, 1));
+        assert_eq!(line_col("Nand(a, b))", 10), (1, 11));
+    }
+
+    #[test]
+    fn line_col_after_newlines() {
+        let input = "And(a,\n  b))";
+        assert_eq!(line_col(input, 7), (2, 1));
+        assert_eq!(line_col(input, 11), (2, 5));
+    }
+
+    #[test]
+    fn line_col_after_other_line_breaks() {
+        let input = "a\r\nb\rc\n\rd";
+        assert_eq!(line_col(input, 0), (1, 1));
+        assert_eq!(line_col(input, 3), (2, 1));
+        assert_eq!(line_col(input, 5), (3, 1));
+        // `\n\r` are two line breaks, unlike `\r\n`.
+        assert_eq!(line_col(input, 8), (5, 1));
+    }
+
+    #[test]
+    fn caret_points_at_the_span() {
+        let span = Span {
+            start: 10,
+            end:   11,
+        };
+        assert_eq!(
+            caret_lines("Nand(a, b))", span),
+            "Nand(a, b))\n          ^\n"
+        );
+        let span = Span { start: 0, end: 3 };
+        assert_eq!(caret_lines("Foo(a)", span), "Foo(a)\n^~~\n");
+    }
+
+    #[test]
+    fn caret_shows_only_the_line_of_the_span() {
+        let input = "And(a,\r\n  b))\nNot(a)";
+        let span = Span {
+            start: 12,
+            end:   13,
+        };
+        assert_eq!(caret_lines(input, span), "  b))\n    ^\n");
+        // Spans are cut off at the end of their line.
+        let span = Span {
+            start: 0,
+            end:   10,
+        };
+        assert_eq!(caret_lines(input, span), "And(a,\n^~~~~~\n");
+        let span = Span {
+            start: 22,
+            end:   22,
+        };
+        assert_eq!(caret_lines(input, span), "Not(a)\n      ^\n");
+    }
+
+    #[test]
+    fn caret_keeps_tabs_before_it() {
+        let span = Span { start: 6, end: 9 };
+        assert_eq!(
+            caret_lines("\tAnd(\tFoo(a))", span),
+            "\tAnd(\tFoo(a))\n\t    \t^~~\n"
+        );
+    }
+
+    #[test]
+    fn line_col_counts_characters() {
+        assert_eq!(line_col("äb", 2), (1, 2));
+        // Offsets inside of a character point to the character.
+        assert_eq!(line_col("äb", 1), (1, 1));
+    }
+
+    #[test]
+    fn line_col_past_the_end() {
+        assert_eq!(line_col("ab", 5), (1, 3));
+    }
+
+    #[test]
+    fn edit_distance_counts_single_edits() {
+        assert_eq!(edit_distance("And", "And"), 0);
+        assert_eq!(edit_distance("Ans", "And"), 1);
+        assert_eq!(edit_distance("An", "And"), 1);
+        assert_eq!(edit_distance("Andd", "And"), 1);
+        assert_eq!(edit_distance("", "Nand"), 4);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
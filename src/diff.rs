@@ -0,0 +1,95 @@
+use crate::tree::Node;
+
+// A place where two trees differ. `path` leads from the root to
+// the sub-trees which differ: each number is the index of the
+// argument to go to next. The sub-trees below that place are
+// the same in both trees until `left` and `right`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffEntry {
+    pub path:  Vec<usize>,
+    pub left:  Node,
+    pub right: Node,
+}
+
+impl std::fmt::Display for DiffEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}: {} vs. {}", self.path, self.left, self.right)
+    }
+}
+
+impl Node {
+    // All places where `self` and `other` differ, from left to
+    // right. Functions with the same id and the same number of
+    // arguments are compared argument by argument, so that only
+    // the innermost differences are reported. There are none
+    // exactly if the trees are equal.
+    pub fn structural_diff(&self, other: &Node) -> Vec<DiffEntry> {
+        fn visit(
+            left: &Node,
+            right: &Node,
+            path: &mut Vec<usize>,
+            diff: &mut Vec<DiffEntry>,
+        ) {
+            match (left, right) {
+                (
+                    Node::Func { id, args },
+                    Node::Func {
+                        id: other_id,
+                        args: other_args,
+                    },
+                ) if id == other_id && args.len() == other_args.len() => {
+                    for (i, (left, right)) in
+                        args.iter().zip(other_args).enumerate()
+                    {
+                        path.push(i);
+                        visit(left, right, path, diff);
+                        path.pop();
+                    }
+                },
+                _ if left == right => {},
+                _ => diff.push(DiffEntry {
+                    path:  path.clone(),
+                    left:  left.clone(),
+                    right: right.clone(),
+                }),
+            }
+        }
+
+        let mut diff = vec![];
+        visit(self, other, &mut vec![], &mut diff);
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Node {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn diff_points_to_the_differing_leaf() {
+        let left = parse("Nand(Nand(a, b), Nand(a, c))");
+        let right = parse("Nand(Nand(a, b), Nand(a, d))");
+        let diff = left.structural_diff(&right);
+        assert_eq!(diff, [DiffEntry {
+            path:  vec![1, 1],
+            left:  parse("c"),
+            right: parse("d"),
+        }]);
+        assert_eq!(diff[0].to_string(), "[1, 1]: c vs. d");
+        assert!(left.structural_diff(&left).is_empty());
+    }
+
+    #[test]
+    fn diff_of_different_functions_stops_there() {
+        let left = parse("Nand(Not(a), And(a, b))");
+        let right = parse("Nand(Not(b), Or(a, b))");
+        let diff = left.structural_diff(&right);
+        let diff: Vec<String> = diff.iter().map(DiffEntry::to_string).collect();
+        assert_eq!(diff, ["[0, 0]: a vs. b", "[1]: And(a, b) vs. Or(a, b)"]);
+        assert!(parse("a").structural_diff(&parse("1"))[0].path.is_empty());
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::names::is_simple;
+use crate::tree::Node;
+
+impl Node {
+    // Draw the tree as a Graphviz digraph. Every function is
+    // a box of its own, but each variable and constant only
+    // appears once, no matter how often it's used. Edges point
+    // from a function to its arguments. Names of variables are
+    // put in quotes if they have to be.
+    pub fn to_dot(&self) -> String {
+        // Returns the name of the graph node for `node`.
+        fn visit(
+            node: &Node,
+            out: &mut String,
+            next_func: &mut usize,
+            leaves: &mut HashSet<String>,
+        ) -> String {
+            match node {
+                Node::Func { id, args } => {
+                    let name = format!("n{next_func}");
+                    *next_func += 1;
+                    writeln!(out, "    {name} [label=\"{id}\", shape=box];")
+                        .unwrap();
+                    for arg in args {
+                        let arg_name = visit(arg, out, next_func, leaves);
+                        writeln!(out, "    {name} -> {arg_name};").unwrap();
+                    }
+                    name
+                },
+                Node::Var { .. } | Node::Const { .. } => {
+                    let (name, shape) = match node {
+                        Node::Var { id } => {
+                            (dot_id(&format!("var_{id}")), "ellipse")
+                        },
+                        _ => (format!("const_{node}"), "plaintext"),
+                    };
+                    if leaves.insert(name.clone()) {
+                        let label = escape(&node.to_string());
+                        writeln!(
+                            out,
+                            "    {name} [label=\"{label}\", shape={shape}];"
+                        )
+                        .unwrap();
+                    }
+                    name
+                },
+            }
+        }
+
+        let mut out = String::from("digraph {\n");
+        visit(self, &mut out, &mut 0, &mut HashSet::new());
+        out.push('}');
+        out
+    }
+}
+
+// `id` as a DOT ID, which is in quotes unless it's simple.
+fn dot_id(id: &str) -> String {
+    if is_simple(id) {
+        id.to_owned()
+    } else {
+        format!("\"{}\"", escape(id))
+    }
+}
+
+// `text` with backslashes and quotes escaped, so that it can go
+// between quotes.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lex::lex;
+    use crate::parse::start;
+
+    #[test]
+    fn dot_of_lowered_and() {
+        let mut tree = start(&mut lex("And(a, b)").peekable()).unwrap();
+        tree.to_nand();
+        assert_eq!(
+            tree.to_dot(),
+            "digraph {\n    \
+             n0 [label=\"Nand\", shape=box];\n    \
+             n1 [label=\"Nand\", shape=box];\n    \
+             var_a [label=\"a\", shape=ellipse];\n    \
+             n1 -> var_a;\n    \
+             var_b [label=\"b\", shape=ellipse];\n    \
+             n1 -> var_b;\n    \
+             n0 -> n1;\n    \
+             n2 [label=\"Nand\", shape=box];\n    \
+             n2 -> var_a;\n    \
+             n2 -> var_b;\n    \
+             n0 -> n2;\n\
+             }"
+        );
+    }
+
+    #[test]
+    fn dot_draws_constants_once() {
+        let tree = start(&mut lex("Nand(1, Not(1))").peekable()).unwrap();
+        let dot = tree.to_dot();
+        assert_eq!(dot.matches("const_1 [label=\"1\"").count(), 1);
+        assert_eq!(dot.matches("-> const_1;").count(), 2);
+    }
+
+    #[test]
+    fn dot_quotes_quoted_variables() {
+        let tree =
+            start(&mut lex("Nand(`my var`, `a\"b`)").peekable()).unwrap();
+        let dot = tree.to_dot();
+        assert!(dot.contains(
+            "    \"var_my var\" [label=\"`my var`\", shape=ellipse];\n    \
+             n0 -> \"var_my var\";\n"
+        ));
+        assert!(dot.contains(
+            "    \"var_a\\\"b\" [label=\"`a\\\"b`\", shape=ellipse];\n"
+        ));
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::parse::Id;
+use crate::tree::Node;
+
+// Expressions with more variables than this have no semantic
+// hash, since their truth tables get too large.
+const MAX_HASH_VARS: usize = 16;
+
+// Expressions with more variables than this aren't checked by
+// trying every assignment, since there are far too many.
+pub(crate) const MAX_TABLE_VARS: usize = 20;
+
+impl Node {
+    // Compute the output of the expression if its variables
+    // have the values in `env`.
+    pub fn eval(&self, env: &HashMap<String, bool>) -> Result<bool, EvalError> {
+        match self {
+            Node::Func { id, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval(env))
+                    .collect::<Result<Vec<bool>, _>>()?;
+                let value = match id {
+                    Id::And => args.iter().all(|&arg| arg),
+                    Id::Or => args.iter().any(|&arg| arg),
+                    Id::Nand => !args.iter().all(|&arg| arg),
+                    Id::Not => !args[0],
+                    Id::Xor => args[0] != args[1],
+                    Id::Nor => !args.iter().any(|&arg| arg),
+                    Id::Xnor => args[0] == args[1],
+                    Id::Imp => !args[0] || args[1],
+                };
+                Ok(value)
+            },
+            Node::Var { id } => env
+                .get(id)
+                .copied()
+                .ok_or_else(|| EvalError::UnboundVariable(id.clone())),
+            Node::Const { value } => Ok(*value),
+        }
+    }
+
+    // Names of all variables in the tree, sorted.
+    pub fn variables(&self) -> BTreeSet<&str> {
+        fn visit<'a>(node: &'a Node, vars: &mut BTreeSet<&'a str>) {
+            match node {
+                Node::Func { args, .. } => {
+                    for arg in args {
+                        visit(arg, vars);
+                    }
+                },
+                Node::Var { id } => {
+                    vars.insert(id);
+                },
+                Node::Const { .. } => {},
+            }
+        }
+
+        let mut vars = BTreeSet::new();
+        visit(self, &mut vars);
+        vars
+    }
+
+    // Whether the output is `1` for every assignment of the
+    // variables. This takes time exponential in their number, so
+    // it fails if there are more than 20 of them.
+    pub fn is_tautology(&self) -> Result<bool, EvalError> {
+        Ok(self.constant_value()? == Some(true))
+    }
+
+    // Whether the output is `0` for every assignment of the
+    // variables. Like `is_tautology`, this fails if there are
+    // more than 20 of them.
+    pub fn is_contradiction(&self) -> Result<bool, EvalError> {
+        Ok(self.constant_value()? == Some(false))
+    }
+
+    // The output if it's the same for every assignment of the
+    // variables, found by trying each assignment once. Like
+    // `is_tautology`, this fails if there are more than 20.
+    pub fn constant_value(&self) -> Result<Option<bool>, EvalError> {
+        let vars: Vec<&str> = self.variables().into_iter().collect();
+        check_table_size(&vars)?;
+        let mut outputs = assignments(&vars)
+            .map(|env| self.eval(&env).expect("all variables are bound"));
+        let first = outputs.next().expect("there is at least one assignment");
+        let constant = outputs.all(|output| output == first).then_some(first);
+        Ok(constant)
+    }
+
+    // Hash of the function which the expression computes, so that
+    // equivalent expressions have the same hash no matter how
+    // they're built. Like for `equivalent`, variables which don't
+    // affect the output don't count, so `Or(x, Not(x))` has the
+    // same hash as `1`. The hash is the same in every run and on
+    // every platform, so it can be stored. This is `None` if there
+    // are more than 16 variables.
+    pub fn semantic_hash(&self) -> Option<u64> {
+        let vars: Vec<&str> = self.variables().into_iter().collect();
+        if vars.len() > MAX_HASH_VARS {
+            return None;
+        }
+        // The `i`th entry is the output for the `i`th assignment.
+        let table: Vec<bool> = assignments(&vars)
+            .map(|env| self.eval(&env).expect("all variables are bound"))
+            .collect();
+        let support: Vec<usize> = (0..vars.len())
+            .filter(|&var| {
+                (0..table.len()).any(|i| table[i] != table[i ^ (1 << var)])
+            })
+            .collect();
+        // The truth table of only the variables in `support`. The
+        // others can have any value, so they're all `0` here.
+        let reduced: Vec<bool> = (0..1usize << support.len())
+            .map(|bits| {
+                let i = support
+                    .iter()
+                    .enumerate()
+                    .filter(|&(j, _)| bits & (1 << j) != 0)
+                    .fold(0, |i, (_, &var)| i | 1 << var);
+                table[i]
+            })
+            .collect();
+
+        // `0xff` is never part of UTF-8, so it can end each name.
+        let mut hash = FNV_OFFSET;
+        for &var in &support {
+            hash = fnv1a(hash, vars[var].as_bytes());
+            hash = fnv1a(hash, &[0xff]);
+        }
+        let outputs: Vec<u8> =
+            reduced.iter().map(|&output| output as u8).collect();
+        Some(fnv1a(hash, &outputs))
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+// Feed `bytes` to the 64 bit FNV-1a hash, which is at `hash` so
+// far. Unlike `DefaultHasher`, it never changes.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+// Fail if trying every assignment of `vars` takes too long.
+pub(crate) fn check_table_size(vars: &[&str]) -> Result<(), EvalError> {
+    if vars.len() > MAX_TABLE_VARS {
+        return Err(EvalError::TooManyVariables {
+            count: vars.len(),
+            limit: MAX_TABLE_VARS,
+        });
+    }
+    Ok(())
+}
+
+// Every possible assignment of values to `vars`. There are
+// `2^n` of them for `n` variables, so `n` must be less than 64.
+pub(crate) fn assignments<'a>(
+    vars: &'a [&str],
+) -> impl Iterator<Item = HashMap<String, bool>> + 'a {
+    let count = 1u64
+        .checked_shl(vars.len() as u32)
+        .filter(|&count| count != 0)
+        .expect("too many variables to list their assignments");
+    (0..count).map(move |bits| {
+        vars.iter()
+            .enumerate()
+            .map(|(i, var)| (var.to_string(), bits & (1 << i) != 0))
+            .collect()
+    })
+}
+
+// Whether `a` and `b` have the same output for every assignment
+// of the variables in either of them. This fails if there are
+// more than 20 variables.
+pub(crate) fn same_truth_table(a: &Node, b: &Node) -> Result<bool, EvalError> {
+    let vars: Vec<&str> =
+        a.variables().union(&b.variables()).copied().collect();
+    check_table_size(&vars)?;
+    let equal = assignments(&vars).all(|env| a.eval(&env) == b.eval(&env));
+    Ok(equal)
+}
+
+// An assignment for which two expressions have different
+// outputs. The variables are sorted by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub assignment: Vec<(String, bool)>,
+    pub a:          bool,
+    pub b:          bool,
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, (id, value)) in self.assignment.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{id}={}", u8::from(*value))?;
+        }
+        if !self.assignment.is_empty() {
+            write!(f, ": ")?;
+        }
+        write!(f, "{} vs. {}", u8::from(self.a), u8::from(self.b))
+    }
+}
+
+// All assignments of the variables in either of `a` and `b`
+// for which their outputs differ, in the order of a truth table.
+// Like `same_truth_table`, this fails if there are more than 20
+// variables.
+pub(crate) fn mismatches(
+    a: &Node,
+    b: &Node,
+) -> Result<Vec<Mismatch>, EvalError> {
+    let vars: Vec<&str> =
+        a.variables().union(&b.variables()).copied().collect();
+    check_table_size(&vars)?;
+    let mismatches = assignments(&vars)
+        .filter_map(|env| {
+            let value_a = a.eval(&env).expect("all variables are bound");
+            let value_b = b.eval(&env).expect("all variables are bound");
+            (value_a != value_b).then(|| Mismatch {
+                assignment: vars
+                    .iter()
+                    .map(|&id| (id.to_owned(), env[id]))
+                    .collect(),
+                a:          value_a,
+                b:          value_b,
+            })
+        })
+        .collect();
+    Ok(mismatches)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    UnboundVariable(String),
+    // Trying every assignment of this many variables takes too
+    // long.
+    TooManyVariables { count: usize, limit: usize },
+}
+
+impl std::fmt::Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::UnboundVariable(id) => {
+                write!(f, "variable '{id}' has no value")
+            },
+            Self::TooManyVariables { count, limit } => write!(
+                f,
+                "expression has {count} variables, but at most {limit} can \
+                 be checked"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::lex;
+    use crate::parse::start;
+
+    fn parse(input: &str) -> Node {
+        start(&mut lex(input).peekable()).unwrap()
+    }
+
+    fn env(values: &[(&str, bool)]) -> HashMap<String, bool> {
+        values.iter().map(|(id, v)| (id.to_string(), *v)).collect()
+    }
+
+    #[test]
+    fn and_agrees_with_its_lowering() {
+        let tree = parse("And(a, b)");
+        let mut lowered = tree.clone();
+        lowered.to_nand();
+        for (a, b) in
+            [(false, false), (false, true), (true, false), (true, true)]
+        {
+            let env = env(&[("a", a), ("b", b)]);
+            assert_eq!(tree.eval(&env), Ok(a && b));
+            assert_eq!(lowered.eval(&env), Ok(a && b));
+        }
+    }
+
+    #[test]
+    fn eval_handles_every_function() {
+        let env = env(&[("a", true), ("b", false)]);
+        assert_eq!(parse("And(a, b, a)").eval(&env), Ok(false));
+        assert_eq!(parse("Or(b, b, a)").eval(&env), Ok(true));
+        assert_eq!(parse("Nand(a, b)").eval(&env), Ok(true));
+        assert_eq!(parse("Not(a)").eval(&env), Ok(false));
+        assert_eq!(parse("Xor(a, b)").eval(&env), Ok(true));
+        assert_eq!(parse("Nor(a, b)").eval(&env), Ok(false));
+        assert_eq!(parse("Xnor(a, b)").eval(&env), Ok(false));
+        assert_eq!(parse("Imp(a, b)").eval(&env), Ok(false));
+        assert_eq!(parse("And(a, 1)").eval(&env), Ok(true));
+    }
+
+    #[test]
+    fn constant_outputs() {
+        assert_eq!(parse("Or(a, Nand(a, a))").is_tautology(), Ok(true));
+        assert_eq!(parse("Imp(And(a, b), a)").is_tautology(), Ok(true));
+        assert_eq!(parse("1").is_tautology(), Ok(true));
+        assert_eq!(parse("And(a, Not(a), b)").is_contradiction(), Ok(true));
+        assert_eq!(parse("And(a, Not(a))").is_tautology(), Ok(false));
+        for input in ["And(a, b)", "Xor(a, 1)", "a"] {
+            assert_eq!(parse(input).constant_value(), Ok(None));
+            assert_eq!(parse(input).is_tautology(), Ok(false));
+            assert_eq!(parse(input).is_contradiction(), Ok(false));
+        }
+    }
+
+    #[test]
+    fn too_many_variables_are_rejected() {
+        let vars: Vec<String> = (0..64).map(|i| format!("v{i}")).collect();
+        let and = parse(&format!("And({})", vars.join(", ")));
+        let or = parse(&format!("Or({})", vars.join(", ")));
+        let e = EvalError::TooManyVariables {
+            count: 64,
+            limit: MAX_TABLE_VARS,
+        };
+        assert_eq!(same_truth_table(&and, &or), Err(e.clone()));
+        assert_eq!(mismatches(&and, &or), Err(e.clone()));
+        assert_eq!(and.is_tautology(), Err(e.clone()));
+        assert_eq!(
+            e.to_string(),
+            "expression has 64 variables, but at most 20 can be checked"
+        );
+    }
+
+    #[test]
+    fn semantic_hash_ignores_structure() {
+        let hash = |input: &str| parse(input).semantic_hash().unwrap();
+        let mut lowered = parse("And(a, b)");
+        lowered.to_nand();
+        assert_eq!(lowered.semantic_hash(), Some(hash("And(a, b)")));
+        assert_eq!(hash("And(a, b)"), hash("Not(Or(Not(b), Not(a)))"));
+        assert_eq!(hash("Or(x, Not(x))"), hash("1"));
+        assert_eq!(hash("Xor(a, And(b, 0))"), hash("a"));
+        assert_ne!(hash("And(a, b)"), hash("Or(a, b)"));
+        assert_ne!(hash("a"), hash("b"));
+        assert_ne!(hash("0"), hash("1"));
+    }
+
+    #[test]
+    fn semantic_hash_is_fnv1a() {
+        assert_eq!(fnv1a(FNV_OFFSET, b""), FNV_OFFSET);
+        assert_eq!(fnv1a(FNV_OFFSET, b"a"), 0xaf63_dc4c_8601_ec8c);
+        // The same in every run, with `b` and `a` in the support.
+        let hash = parse("Or(b, a)").semantic_hash().unwrap();
+        let mut expected = fnv1a(FNV_OFFSET, b"a\xffb\xff");
+        expected = fnv1a(expected, &[0, 1, 1, 1]);
+        assert_eq!(hash, expected);
+    }
+
+    #[test]
+    fn semantic_hash_needs_few_variables() {
+        let vars: Vec<String> = (0..17).map(|i| format!("x{i}")).collect();
+        let wide = parse(&format!("And({})", vars.join(", ")));
+        assert_eq!(wide.semantic_hash(), None);
+        let narrow = parse(&format!("And({})", vars[..16].join(", ")));
+        assert!(narrow.semantic_hash().is_some());
+    }
+
+    #[test]
+    fn mismatches_of_and_and_or() {
+        let found =
+            mismatches(&parse("And(a, b)"), &parse("Or(b, a)")).unwrap();
+        let rows: Vec<String> = found.iter().map(Mismatch::to_string).collect();
+        assert_eq!(rows, ["a=1, b=0: 0 vs. 1", "a=0, b=1: 0 vs. 1"]);
+        assert_eq!(mismatches(&parse("Not(Not(a))"), &parse("a")), Ok(vec![]));
+        assert_eq!(
+            mismatches(&parse("1"), &parse("0")).unwrap()[0].to_string(),
+            "1 vs. 0"
+        );
+    }
+
+    #[test]
+    fn variables_are_sorted_and_distinct() {
+        let tree = parse("Or(b, And(a, b), Not(c), 1)");
+        assert_eq!(tree.variables().into_iter().collect::<Vec<_>>(), [
+            "a", "b", "c"
+        ]);
+    }
+
+    #[test]
+    fn assignments_cover_all_combinations() {
+        let envs: Vec<_> = assignments(&["a", "b"]).collect();
+        assert_eq!(envs.len(), 4);
+        assert!(envs.contains(&env(&[("a", true), ("b", false)])));
+        assert_eq!(assignments(&[]).count(), 1);
+    }
+
+    #[test]
+    fn eval_reports_unbound_variable() {
+        assert_eq!(
+            parse("Or(a, c)").eval(&env(&[("a", false)])),
+            Err(EvalError::UnboundVariable("c".to_owned()))
+        );
+    }
+}
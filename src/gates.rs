@@ -0,0 +1,212 @@
+use std::collections::BTreeSet;
+
+use crate::parse::Id;
+use crate::tree::Node;
+
+// Gates which some target has natively, so they don't need to
+// be lowered. Everything can be built from `Nand`s, so `Nand`
+// is part of the set unless it's built with `only`. The default
+// set has nothing else, which is what `to_nand` lowers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GateSet {
+    ids: BTreeSet<Id>,
+}
+
+impl Default for GateSet {
+    fn default() -> Self {
+        Self::only([Id::Nand])
+    }
+}
+
+impl GateSet {
+    // A set of exactly the gates in `ids`. Without `Nand` in
+    // there, gates which aren't in the set can't be lowered.
+    pub fn only(ids: impl IntoIterator<Item = Id>) -> Self {
+        Self {
+            ids: ids.into_iter().collect(),
+        }
+    }
+
+    // Same set, but with `id` in it.
+    pub fn with(mut self, id: Id) -> Self {
+        self.ids.insert(id);
+        self
+    }
+
+    pub fn contains(&self, id: Id) -> bool {
+        self.ids.contains(&id)
+    }
+}
+
+// Why a tree couldn't be lowered to some set of gates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LowerError {
+    // The tree uses a function which isn't in the set, and
+    // `Nand`, which functions are lowered to, isn't either.
+    Unsupported(Id),
+}
+
+impl std::fmt::Display for LowerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Unsupported(id) => {
+                write!(
+                    f,
+                    "function '{id}' isn't in the target gates, and 'Nand' \
+                     isn't available to build it"
+                )
+            },
+        }
+    }
+}
+
+impl std::error::Error for LowerError {}
+
+impl FromIterator<Id> for GateSet {
+    fn from_iter<I: IntoIterator<Item = Id>>(ids: I) -> Self {
+        let mut set = Self::default();
+        set.ids.extend(ids);
+        set
+    }
+}
+
+impl Node {
+    // Lower `self` to the gates in `target`, like `to_gates` does.
+    // Functions which aren't in `target` are lowered to `Nand`s,
+    // so if `Nand` isn't in there either, the first such function
+    // is reported and `self` is left as it was. This is the case
+    // even if the gates in `target` could build the function in
+    // some other way, like `Or` from `And` and `Not`.
+    pub fn try_lower(&mut self, target: &GateSet) -> Result<(), LowerError> {
+        fn check(node: &Node, target: &GateSet) -> Result<(), LowerError> {
+            if let Node::Func { id, args } = node {
+                if !target.contains(*id) && !target.contains(Id::Nand) {
+                    return Err(LowerError::Unsupported(*id));
+                }
+                args.iter().try_for_each(|arg| check(arg, target))?;
+            }
+            Ok(())
+        }
+
+        check(self, target)?;
+        self.to_gates(target);
+        Ok(())
+    }
+
+    // Lower every function which isn't in `gates` to `Nand`s, like
+    // `to_nand` does, but leave the others as they are. `And`s
+    // and `Or`s which are kept keep all their arguments, too. This
+    // assumes `Nand` is in `gates`; use `try_lower` if it isn't.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_gates(&mut self, gates: &GateSet) {
+        self.rewrite_post_order(|id, args| {
+            if gates.contains(id) {
+                Node::Func { id, args }
+            } else {
+                Node::lower_wide_func(id, args)
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::assignments;
+    use crate::lex::lex;
+    use crate::parse::start;
+
+    fn parse(input: &str) -> Node {
+        start(&mut lex(input).peekable()).unwrap()
+    }
+
+    #[test]
+    fn default_gates_lower_like_to_nand() {
+        let input = "Or(And(a, b, c), Not(Xor(a, 0)), Imp(a, Xnor(b, c)))";
+        let mut tree = parse(input);
+        tree.to_gates(&GateSet::default());
+        let mut lowered = parse(input);
+        lowered.to_nand();
+        assert_eq!(tree, lowered);
+    }
+
+    #[test]
+    fn allowed_gates_are_kept() {
+        let gates = GateSet::default().with(Id::And).with(Id::Or);
+        let mut tree = parse("And(a, Xor(b, c))");
+        tree.to_gates(&gates);
+        let mut xor = parse("Xor(b, c)");
+        xor.to_nand();
+        assert_eq!(tree, Node::Func {
+            id:   Id::And,
+            args: vec![parse("a"), xor],
+        });
+    }
+
+    #[test]
+    fn lowering_only_some_gates_keeps_the_function() {
+        let gates: GateSet = [Id::Not, Id::Or].into_iter().collect();
+        let input = "Or(And(a, b, c), Not(Xor(a, b)), Nor(c, a), b)";
+        let mut tree = parse(input);
+        tree.to_gates(&gates);
+        assert!(
+            matches!(tree, Node::Func { id: Id::Or, ref args } if args.len() == 4)
+        );
+        for env in assignments(&["a", "b", "c"]) {
+            assert_eq!(tree.eval(&env), parse(input).eval(&env));
+        }
+    }
+
+    #[test]
+    fn impossible_target_is_unsupported() {
+        // Without `Not`, `And`s and `Or`s can't build `Not(a)`.
+        let gates = GateSet::only([Id::And, Id::Or]);
+        let mut tree = parse("And(Or(a, b), Not(a))");
+        let before = tree.clone();
+        assert_eq!(
+            tree.try_lower(&gates),
+            Err(LowerError::Unsupported(Id::Not))
+        );
+        assert_eq!(tree, before);
+        assert_eq!(
+            LowerError::Unsupported(Id::Not).to_string(),
+            "function 'Not' isn't in the target gates, and 'Nand' isn't \
+             available to build it"
+        );
+
+        // Without `Nand`, even `Nand` itself is unsupported.
+        let mut tree = parse("Nand(a, b)");
+        assert_eq!(
+            tree.try_lower(&GateSet::only([])),
+            Err(LowerError::Unsupported(Id::Nand))
+        );
+    }
+
+    #[test]
+    fn sets_with_the_same_gates_are_equal() {
+        assert_eq!(GateSet::only([Id::Nand]), GateSet::default());
+        assert_eq!(
+            GateSet::only([Id::Nand, Id::Or]),
+            [Id::Or].into_iter().collect()
+        );
+        assert_ne!(GateSet::only([Id::Or]), [Id::Or].into_iter().collect());
+    }
+
+    #[test]
+    fn possible_targets_lower_like_to_gates() {
+        let input = "And(Not(a), Or(a, b))";
+        let mut tree = parse(input);
+        assert_eq!(
+            tree.try_lower(&GateSet::only([Id::And, Id::Not, Id::Or])),
+            Ok(())
+        );
+        assert_eq!(tree, parse(input));
+
+        let gates = GateSet::default().with(Id::Not);
+        let mut tree = parse(input);
+        assert_eq!(tree.try_lower(&gates), Ok(()));
+        let mut lowered = parse(input);
+        lowered.to_gates(&gates);
+        assert_eq!(tree, lowered);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::parse::Id;
+use crate::tree::Node;
+
+// Why a JSON tree couldn't be read.
+#[derive(Debug)]
+pub enum JsonError {
+    // The input isn't JSON, or not a tree in the format which
+    // `--format json` prints.
+    Invalid(serde_json::Error),
+    // A function has a number of arguments it doesn't accept.
+    ArgCount { id: Id, found: usize },
+}
+
+impl std::fmt::Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Invalid(e) => write!(f, "invalid JSON tree: {e}"),
+            Self::ArgCount { id, found } => {
+                write!(f, "function '{id}' can't take {found} arguments")
+            },
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+impl Node {
+    // Read a tree in the format which serializing a `Node`
+    // produces. This skips the parser, so there are no `let`s
+    // or user-defined functions, only the built-in ones.
+    pub fn from_json(input: &str) -> Result<Node, JsonError> {
+        fn check(node: &Node) -> Result<(), JsonError> {
+            if let Node::Func { id, args } = node {
+                if !id.accepts(args.len()) {
+                    return Err(JsonError::ArgCount {
+                        id:    *id,
+                        found: args.len(),
+                    });
+                }
+                args.iter().try_for_each(check)?;
+            }
+            Ok(())
+        }
+
+        let tree = serde_json::from_str(input).map_err(JsonError::Invalid)?;
+        check(&tree)?;
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::lex;
+    use crate::parse::start;
+
+    #[test]
+    fn json_and_lowers_like_parsed_and() {
+        let json = r#"{"Func": {"id": "And", "args": [
+            {"Var": {"id": "a"}},
+            {"Var": {"id": "b"}}
+        ]}}"#;
+        let mut from_json = Node::from_json(json).unwrap();
+        let mut parsed = start(&mut lex("And(a, b)").peekable()).unwrap();
+        assert_eq!(from_json, parsed);
+        from_json.to_nand();
+        parsed.to_nand();
+        assert_eq!(from_json, parsed);
+    }
+
+    #[test]
+    fn json_with_wrong_arg_count_is_rejected() {
+        let json = r#"{"Func": {"id": "Not", "args": [
+            {"Var": {"id": "a"}},
+            {"Const": {"value": true}}
+        ]}}"#;
+        let e = Node::from_json(json).unwrap_err();
+        assert_eq!(e.to_string(), "function 'Not' can't take 2 arguments");
+        assert!(matches!(
+            Node::from_json("And(a, b)"),
+            Err(JsonError::Invalid(_))
+        ));
+    }
+}
@@ -1,6 +1,32 @@
-use logos::Logos;
+use std::ops::Range;
+
+use logos::{Lexer, Logos};
+
+// Settings for `lex_with`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LexOptions {
+    // Identifiers which are longer than this many bytes are
+    // lexical errors. This keeps huge inputs from being copied
+    // into just as huge identifiers.
+    pub max_ident_len: usize,
+    // The character which separates arguments instead of `,`.
+    // It must not be whitespace or part of any other token, like
+    // `;`, or translating fails. With another delimiter, `,` is a
+    // lexical error.
+    pub delimiter:     char,
+}
+
+impl Default for LexOptions {
+    fn default() -> Self {
+        Self {
+            max_ident_len: 1024,
+            delimiter:     ',',
+        }
+    }
+}
 
 #[derive(Logos, Debug, Clone, PartialEq, Eq)]
+#[logos(extras = LexOptions)]
 pub enum Token {
     #[token("(")]
     LParen,
@@ -8,20 +34,162 @@ pub enum Token {
     RParen,
     #[token(",")]
     Delim,
+    #[token("let")]
+    Let,
+    #[token("=")]
+    Eq,
+    #[token("in")]
+    In,
+    #[token("def")]
+    Def,
+    // Infix operators, which are an alternative to calling
+    // `And`, `Or`, `Xor`, `Not` and `Nand`. The logical symbols
+    // mean the same as their ASCII counterparts.
+    #[token("&")]
+    #[token("∧")]
+    AndOp,
+    #[token("|")]
+    #[token("∨")]
+    OrOp,
+    #[token("^")]
+    XorOp,
+    #[token("~")]
+    #[token("¬")]
+    NotOp,
+    #[token("⊼")]
+    NandOp,
     // A function's identifier must be at least two characters
     // long. The first character must be a capital letter.
     // The case of all the subsequent letters is irrelevant.
-    #[regex("[A-Z][A-Za-z]+", |lex| lex.slice().to_string())]
+    #[regex("[A-Z][A-Za-z]+", ident)]
     FuncIdent(String),
     // A variable's identifier must be at least one character
     // long. The first character must be a lowercase letter.
-    // All subsequent characters can be either lowercase letters
-    // or underscores.
-    #[regex("[a-z][a-z_]*", |lex| lex.slice().to_string())]
+    // All subsequent characters can be either lowercase letters,
+    // digits or underscores. Any other name can be written
+    // between backticks. In there, `\``, `\\`, `\n` and `\r` stand
+    // for a backtick, a backslash and line breaks, which can't
+    // be written as they are.
+    #[regex("[a-z][a-z0-9_]*", ident)]
+    #[regex(r"`([^`\\\n\r]|\\[^\n\r])*`", quoted_ident)]
     VarIdent(String),
+    // The boolean constants are written as `0` and `1`.
+    #[token("0", |_| false)]
+    #[token("1", |_| true)]
+    Const(bool),
+    // Comments start with `#` and go to the end of the line.
+    // They are skipped just like whitespace. A digit followed
+    // by more characters of an identifier is an error, instead
+    // of a constant followed by a variable.
     #[error]
     #[regex(r"[ \t\n\r\f]+", logos::skip)]
-    LexError,
+    #[regex(r"#[^\n]*", logos::skip)]
+    #[regex("[0-9][A-Za-z0-9_]+")]
+    Error,
+    // Input which isn't any of the tokens above. `lex` turns
+    // every `Error` into this, together with the start of the
+    // text, which is cut off after `MAX_ERROR_TEXT` characters.
+    LexError(String),
+    // An identifier which is longer than the `max_ident_len` of
+    // the `LexOptions`, which is kept here.
+    IdentTooLong(usize),
+}
+
+// How many characters of the input a `Token::LexError` keeps.
+const MAX_ERROR_TEXT: usize = 16;
+
+// `text` for an error message, with at most `MAX_ERROR_TEXT`
+// characters and `…` in place of the rest.
+fn error_text(text: &str) -> String {
+    match text.char_indices().nth(MAX_ERROR_TEXT) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_owned(),
+    }
+}
+
+// The identifier which was just read, unless it's too long.
+fn ident(lex: &mut Lexer<Token>) -> Option<String> {
+    let slice = lex.slice();
+    (slice.len() <= lex.extras.max_ident_len).then(|| slice.to_owned())
+}
+
+// Same as `ident`, but without the backticks around it and with
+// escapes replaced by what they stand for. The limit is on the
+// length with the escapes.
+fn quoted_ident(lex: &mut Lexer<Token>) -> Option<String> {
+    let slice = lex.slice();
+    let quoted = &slice[1..slice.len() - 1];
+    if quoted.len() > lex.extras.max_ident_len {
+        return None;
+    }
+    let mut ident = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            ident.push(c);
+            continue;
+        }
+        // The regex makes sure that something follows.
+        match chars.next() {
+            Some('n') => ident.push('\n'),
+            Some('r') => ident.push('\r'),
+            Some(c @ ('`' | '\\')) => ident.push(c),
+            // Other backslashes are kept as they are.
+            Some(c) => ident.extend(['\\', c]),
+            None => ident.push('\\'),
+        }
+    }
+    Some(ident)
+}
+
+// Whether `text`, which isn't a token, is an identifier that
+// `ident` or `quoted_ident` rejected for being longer than
+// `limit`. Nothing else which isn't a token looks like one.
+fn is_long_ident(text: &str, limit: usize) -> bool {
+    let quoted =
+        text.len() >= 2 && text.starts_with('`') && text.ends_with('`');
+    let plain = text.starts_with(|c: char| c.is_ascii_alphabetic());
+    text.len() > limit && (plain || quoted)
+}
+
+// Whether `c` can be `LexOptions::delimiter`. Whitespace and
+// characters which start comments or are part of other tokens
+// can't be told apart from what they usually mean.
+pub(crate) fn is_valid_delimiter(c: char) -> bool {
+    let mut text = [0; 4];
+    let is_token =
+        Token::lexer(c.encode_utf8(&mut text)).next() != Some(Token::Error);
+    !c.is_whitespace()
+        && !c.is_alphanumeric()
+        && !matches!(c, '_' | '`' | '#')
+        && (c == ',' || !is_token)
+}
+
+// `id` in backticks, with escapes for what can't be in there.
+// Reading it again gives `id` back, whatever it is.
+pub(crate) fn quote(id: &str) -> String {
+    let mut quoted = String::with_capacity(id.len() + 2);
+    quoted.push('`');
+    for c in id.chars() {
+        match c {
+            '`' | '\\' => quoted.extend(['\\', c]),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('`');
+    quoted
+}
+
+// Whether `id` has to be put in backticks to be read as the
+// same variable again.
+pub(crate) fn needs_quotes(id: &str) -> bool {
+    let mut chars = id.chars();
+    let plain = chars.next().is_some_and(|c| c.is_ascii_lowercase())
+        && chars
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    !plain || matches!(id, "let" | "in" | "def")
 }
 
 impl std::fmt::Display for Token {
@@ -30,9 +198,23 @@ impl std::fmt::Display for Token {
             Self::LParen => write!(f, "'('"),
             Self::RParen => write!(f, "')'"),
             Self::Delim => write!(f, "','"),
+            Self::Let => write!(f, "'let'"),
+            Self::Eq => write!(f, "'='"),
+            Self::In => write!(f, "'in'"),
+            Self::Def => write!(f, "'def'"),
+            Self::AndOp => write!(f, "'&'"),
+            Self::OrOp => write!(f, "'|'"),
+            Self::XorOp => write!(f, "'^'"),
+            Self::NotOp => write!(f, "'~'"),
+            Self::NandOp => write!(f, "'⊼'"),
             Self::FuncIdent(id) => write!(f, "function '{id}'"),
             Self::VarIdent(id) => write!(f, "variable '{id}'"),
-            Self::LexError => write!(f, "lexical error"),
+            Self::Const(value) => write!(f, "constant '{}'", u8::from(*value)),
+            Self::Error => write!(f, "lexical error"),
+            Self::LexError(text) => write!(f, "'{text}'"),
+            Self::IdentTooLong(limit) => {
+                write!(f, "identifier longer than {limit} bytes")
+            },
         }
     }
 }
@@ -45,3 +227,318 @@ impl AsRef<str> for Token {
         }
     }
 }
+
+// Byte range of a token in the input.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end:   usize,
+}
+
+impl From<Range<usize>> for Span {
+    fn from(range: Range<usize>) -> Self {
+        Self {
+            start: range.start,
+            end:   range.end,
+        }
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+// A value together with the span of the input it was read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spanned<T> {
+    pub inner: T,
+    pub span:  Span,
+}
+
+impl<T: std::fmt::Display> std::fmt::Display for Spanned<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+// Split `input` into tokens which remember their position.
+pub fn lex(input: &str) -> impl Iterator<Item = Spanned<Token>> + '_ {
+    lex_with(input, LexOptions::default())
+}
+
+// Same as `lex`, but with other options than the default ones.
+pub fn lex_with(
+    input: &str,
+    options: LexOptions,
+) -> impl Iterator<Item = Spanned<Token>> + '_ {
+    Token::lexer_with_extras(input, options).spanned().map(
+        move |(inner, span)| {
+            let text = &input[span.clone()];
+            let is_delimiter = text.chars().eq([options.delimiter]);
+            let inner = match inner {
+                Token::Error if is_delimiter => Token::Delim,
+                Token::Delim if !is_delimiter => {
+                    Token::LexError(error_text(text))
+                },
+                Token::Error if is_long_ident(text, options.max_ident_len) => {
+                    Token::IdentTooLong(options.max_ident_len)
+                },
+                Token::Error => Token::LexError(error_text(text)),
+                inner => inner,
+            };
+            Spanned {
+                inner,
+                span: span.into(),
+            }
+        },
+    )
+}
+
+// Input which isn't any token, together with where it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexError {
+    pub text: String,
+    pub span: Span,
+}
+
+impl std::fmt::Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "invalid token '{}' at {}", self.text, self.span)
+    }
+}
+
+impl std::error::Error for LexError {}
+
+/// Split `input` into tokens like the parser sees them. Input
+/// which isn't a token is an error instead of a
+/// `Token::LexError`.
+///
+/// ```
+/// use nandu::{tokens, Span, Token};
+///
+/// let mut tokens = tokens("Not(a");
+/// let not = tokens.next().unwrap().unwrap();
+/// assert_eq!(not.inner, Token::FuncIdent("Not".to_owned()));
+/// assert_eq!(not.span, Span { start: 0, end: 3 });
+/// assert_eq!(tokens.count(), 2);
+/// ```
+pub fn tokens(
+    input: &str,
+) -> impl Iterator<Item = Result<Spanned<Token>, LexError>> + '_ {
+    lex(input).map(|token| match token.inner {
+        Token::LexError(text) => Err(LexError {
+            text,
+            span: token.span,
+        }),
+        Token::IdentTooLong(_) => Err(LexError {
+            text: error_text(&input[token.span.start..token.span.end]),
+            span: token.span,
+        }),
+        _ => Ok(token),
+    })
+}
+
+// Attach an empty span to a token. This is used in tests
+// which construct token streams by hand.
+#[cfg(test)]
+pub(crate) fn unspanned(token: Token) -> Spanned<Token> {
+    Spanned {
+        inner: token,
+        span:  Span::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<Token> {
+        lex(input).map(|token| token.inner).collect()
+    }
+
+    fn lex_error(text: &str) -> Token {
+        Token::LexError(text.to_owned())
+    }
+
+    #[test]
+    fn tokens_of_and_with_spans() {
+        let spanned: Vec<(Token, Span)> = super::tokens("And(a, b)")
+            .map(|token| {
+                let token = token.unwrap();
+                (token.inner, token.span)
+            })
+            .collect();
+        assert_eq!(spanned, [
+            (Token::FuncIdent("And".to_owned()), Span {
+                start: 0,
+                end:   3,
+            }),
+            (Token::LParen, Span { start: 3, end: 4 }),
+            (Token::VarIdent("a".to_owned()), Span { start: 4, end: 5 }),
+            (Token::Delim, Span { start: 5, end: 6 }),
+            (Token::VarIdent("b".to_owned()), Span { start: 7, end: 8 }),
+            (Token::RParen, Span { start: 8, end: 9 }),
+        ]);
+    }
+
+    #[test]
+    fn tokens_report_illegal_characters() {
+        let tokens: Vec<_> = super::tokens("a @ b").collect();
+        assert_eq!(tokens.len(), 3);
+        let error = LexError {
+            text: "@".to_owned(),
+            span: Span { start: 2, end: 3 },
+        };
+        assert_eq!(tokens[1], Err(error.clone()));
+        assert_eq!(error.to_string(), "invalid token '@' at 2..3");
+        assert!(tokens[0].is_ok() && tokens[2].is_ok());
+    }
+
+    #[test]
+    fn unknown_symbols_keep_their_text() {
+        assert_eq!(tokens("a + b"), [
+            Token::VarIdent("a".to_owned()),
+            lex_error("+"),
+            Token::VarIdent("b".to_owned()),
+        ]);
+        assert_eq!(lex_error("+").to_string(), "'+'");
+    }
+
+    #[test]
+    fn other_delimiters_replace_the_comma() {
+        let options = LexOptions {
+            delimiter: ';',
+            ..Default::default()
+        };
+        let tokens = |input| -> Vec<Token> {
+            lex_with(input, options).map(|token| token.inner).collect()
+        };
+        assert_eq!(tokens("a;b;;"), [
+            Token::VarIdent("a".to_owned()),
+            Token::Delim,
+            Token::VarIdent("b".to_owned()),
+            Token::Delim,
+            Token::Delim,
+        ]);
+        assert_eq!(tokens("a, b"), [
+            Token::VarIdent("a".to_owned()),
+            lex_error(","),
+            Token::VarIdent("b".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn logical_symbols_are_operators() {
+        assert_eq!(tokens("∧ ∨ ¬ ⊼"), [
+            Token::AndOp,
+            Token::OrOp,
+            Token::NotOp,
+            Token::NandOp,
+        ]);
+        assert_eq!(tokens("a∧b"), tokens("a & b"));
+        assert_eq!(tokens("¬a ∨ b"), tokens("~a | b"));
+    }
+
+    #[test]
+    fn spans_of_logical_symbols_are_in_bytes() {
+        let spans: Vec<Span> = lex("¬a ⊼ b").map(|token| token.span).collect();
+        assert_eq!(spans, [
+            Span { start: 0, end: 2 },
+            Span { start: 2, end: 3 },
+            Span { start: 4, end: 7 },
+            Span { start: 8, end: 9 },
+        ]);
+    }
+
+    #[test]
+    fn variables_may_contain_digits() {
+        assert_eq!(tokens("a1"), [Token::VarIdent("a1".to_owned())]);
+        assert_eq!(tokens("clk_0"), [Token::VarIdent("clk_0".to_owned())]);
+    }
+
+    #[test]
+    fn quoted_variables_may_contain_anything() {
+        assert_eq!(tokens("`my var`"), [Token::VarIdent("my var".to_owned())]);
+        assert_eq!(tokens("`a(b, c)`"), [Token::VarIdent(
+            "a(b, c)".to_owned()
+        )]);
+        assert_eq!(tokens("``"), [Token::VarIdent(String::new())]);
+        assert_eq!(tokens(r"`a\`b\\c\nd\re\f`"), [Token::VarIdent(
+            "a`b\\c\nd\re\\f".to_owned()
+        )]);
+        assert_eq!(tokens("`a\nb`"), [
+            lex_error("`a"),
+            Token::VarIdent("b".to_owned()),
+            lex_error("`"),
+        ]);
+    }
+
+    #[test]
+    fn identifiers_must_not_start_with_digits() {
+        assert_eq!(tokens("1a"), [lex_error("1a")]);
+        assert_eq!(tokens("01"), [lex_error("01")]);
+        assert_eq!(tokens("0, 1"), [
+            Token::Const(false),
+            Token::Delim,
+            Token::Const(true)
+        ]);
+    }
+
+    #[test]
+    fn identifiers_must_not_be_too_long() {
+        let options = LexOptions {
+            max_ident_len: 3,
+            ..Default::default()
+        };
+        let short: Vec<Token> = lex_with("abc abcd Abc Abcd", options)
+            .map(|t| t.inner)
+            .collect();
+        assert_eq!(short, [
+            Token::VarIdent("abc".to_owned()),
+            Token::IdentTooLong(3),
+            Token::FuncIdent("Abc".to_owned()),
+            Token::IdentTooLong(3),
+        ]);
+        let quoted: Vec<Token> =
+            lex_with("`a b` `a  b`", options).map(|t| t.inner).collect();
+        assert_eq!(quoted, [
+            Token::VarIdent("a b".to_owned()),
+            Token::IdentTooLong(3)
+        ]);
+
+        let long = "a".repeat(LexOptions::default().max_ident_len + 1);
+        assert_eq!(tokens(&long), [Token::IdentTooLong(1024)]);
+        assert_eq!(
+            Token::IdentTooLong(1024).to_string(),
+            "identifier longer than 1024 bytes"
+        );
+        let errors: Vec<_> = super::tokens(&long).collect();
+        assert_eq!(errors, [Err(LexError {
+            text: "aaaaaaaaaaaaaaaa…".to_owned(),
+            span: Span {
+                start: 0,
+                end:   1025,
+            },
+        })]);
+    }
+
+    #[test]
+    fn errors_keep_only_the_start_of_the_text() {
+        assert_eq!(tokens("1abcdefghijklmno"), [lex_error("1abcdefghijklmno")]);
+        assert_eq!(tokens("1abcdefghijklmnop"), [lex_error(
+            "1abcdefghijklmno…"
+        )]);
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        assert_eq!(tokens("# Only a comment"), []);
+        assert_eq!(tokens("a # (b, c)) \n, d"), [
+            Token::VarIdent("a".to_owned()),
+            Token::Delim,
+            Token::VarIdent("d".to_owned()),
+        ]);
+    }
+}
@@ -1,30 +1,437 @@
-#![feature(test)]
+// The benchmarks need nightly, but nothing else does, so
+// the library also builds on stable and for other targets.
+#![cfg_attr(test, feature(test))]
 
+#[cfg(test)]
 extern crate test;
 
+mod annotate;
+mod blif;
+mod cost;
+mod dag;
+mod diagnostic;
+mod diff;
+mod dot;
+mod eval;
+mod gates;
+#[cfg(feature = "serde")]
+mod json;
 mod lex;
+mod names;
+mod netlist;
 mod parse;
+mod program;
+mod python;
+mod random;
+mod smtlib;
+mod translator;
 mod tree;
+mod verilog;
+#[cfg(feature = "wasm")]
+mod wasm;
 
-use logos::Logos;
+use std::io::{self, BufRead, Write};
+use std::time::{Duration, Instant};
 
-use crate::lex::Token;
-use crate::parse::{start, ParseError};
+pub use crate::annotate::SourceMap;
+pub use crate::cost::CostModel;
+pub use crate::dag::{Dag, DagNode, NodeId, StructureError};
+pub use crate::diagnostic::{caret_lines, line_col};
+pub use crate::diff::DiffEntry;
+use crate::eval::{check_table_size, same_truth_table};
+pub use crate::eval::{EvalError, Mismatch};
+pub use crate::gates::{GateSet, LowerError};
+#[cfg(feature = "serde")]
+pub use crate::json::JsonError;
+use crate::lex::{is_valid_delimiter, lex, lex_with};
+pub use crate::lex::{tokens, LexError, LexOptions, Span, Spanned, Token};
+pub use crate::netlist::{Gate, GateId, Netlist, Signal};
+use crate::parse::{
+    start, start_collect, start_multi, start_multi_mapped, start_program,
+    start_with,
+};
+pub use crate::parse::{Id, ParseError, ParseOptions};
+pub use crate::program::{Def, Program};
+pub use crate::random::random_expr;
+pub use crate::translator::Translator;
+pub use crate::tree::{ExprStats, Node, SizeMetrics};
+#[cfg(feature = "wasm")]
+pub use crate::wasm::translate_wasm;
 
 type Result<T> = std::result::Result<T, ParseError>;
 
 pub fn translate(input: impl AsRef<str>) -> Result<String> {
-    fn inner(input: &str) -> Result<String> {
-        let mut lex = Token::lexer(input).peekable();
+    translate_with(input, &TranslateOptions::default())
+}
+
+// Settings for `translate_with`. The default settings
+// translate just like `translate` does.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TranslateOptions {
+    // Propagate constants with `Node::fold` before lowering.
+    pub fold:     bool,
+    // Apply the identities of `Node::simplify` after lowering.
+    pub simplify: bool,
+    // Print the result over multiple lines with indentation.
+    pub pretty:   bool,
+    pub parse:    ParseOptions,
+    pub lex:      LexOptions,
+}
+
+pub fn translate_with(
+    input: impl AsRef<str>,
+    options: &TranslateOptions,
+) -> Result<String> {
+    fn inner(input: &str, options: &TranslateOptions) -> Result<String> {
+        if !is_valid_delimiter(options.lex.delimiter) {
+            return Err(ParseError::InvalidDelimiter(options.lex.delimiter));
+        }
+        let mut lex = lex_with(input, options.lex).peekable();
 
-        let mut ast = start(&mut lex)?;
+        let mut ast = start_with(&mut lex, options.parse)?;
+        if options.fold {
+            ast.fold();
+        }
         ast.to_nand();
-        let nand_string = ast.to_string();
+        if options.simplify {
+            ast.simplify();
+        }
+        let nand_string = if options.pretty {
+            format!("{ast:#}")
+        } else {
+            ast.to_string()
+        };
 
         Ok(nand_string)
     }
 
-    inner(input.as_ref())
+    inner(input.as_ref(), options)
+}
+
+// Settings for `translate_stream_with`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamOptions {
+    pub translate:      TranslateOptions,
+    // Stop at the first line which can't be translated, instead
+    // of writing the error and going on with the next line.
+    pub abort_on_error: bool,
+}
+
+// Translate each line of `reader` as a separate expression and
+// write the result to `writer` as soon as it's done. Only one
+// line is kept in memory at a time. Lines with errors are
+// reported with their line number in place of the output.
+pub fn translate_stream(
+    reader: impl BufRead,
+    writer: impl Write,
+) -> io::Result<()> {
+    translate_stream_with(reader, writer, &StreamOptions::default())
+}
+
+// Same as `translate_stream`, but with other options than the
+// default ones. If `abort_on_error` is set, the first error is
+// returned as an `io::Error` of kind `InvalidData`.
+pub fn translate_stream_with(
+    reader: impl BufRead,
+    mut writer: impl Write,
+    options: &StreamOptions,
+) -> io::Result<()> {
+    let mut lines = LineTranslator::new(options.translate.clone());
+    for line in reader.lines() {
+        for result in lines.translate(&line?) {
+            match result {
+                Ok(nand) => writeln!(writer, "{nand}")?,
+                Err(message) if options.abort_on_error => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        message,
+                    ));
+                },
+                Err(message) => writeln!(writer, "{message}")?,
+            }
+        }
+    }
+    Ok(())
+}
+
+// Translates the lines of a stream one at a time, for callers
+// which read the lines themselves, like a REPL does. It counts
+// the lines, so that errors can say where they are.
+#[derive(Debug, Clone, Default)]
+pub struct LineTranslator {
+    options: TranslateOptions,
+    line:    usize,
+}
+
+impl LineTranslator {
+    pub fn new(options: TranslateOptions) -> Self {
+        Self { options, line: 0 }
+    }
+
+    // Translate `text`, which `BufRead::lines` read as one line.
+    // That already splits at `\n` and `\r\n`, but a single `\r`
+    // ends a line too, so there may be more than one. Lines
+    // without any tokens, like empty lines or comments, have no
+    // expression to translate and are skipped. Errors are the
+    // messages to report, with the line number in them.
+    pub fn translate(
+        &mut self,
+        text: &str,
+    ) -> Vec<std::result::Result<String, String>> {
+        let mut results = vec![];
+        for line in text.split('\r') {
+            self.line += 1;
+            if lex(line).next().is_none() {
+                continue;
+            }
+            let i = self.line;
+            results.push(translate_with(line, &self.options).map_err(|e| {
+                match e.span() {
+                    Some(span) => {
+                        let (_, col) = line_col(line, span.start);
+                        format!("Error at line {i}, col {col}: {e}")
+                    },
+                    None => format!("Error at line {i}: {e}"),
+                }
+            }));
+        }
+        results
+    }
+
+    // Number of lines translated so far, including the skipped
+    // ones.
+    pub fn lines(&self) -> usize {
+        self.line
+    }
+}
+
+/// Parse `input` into a tree without lowering it, so that it
+/// can be inspected or transformed before it's translated.
+///
+/// ```
+/// use nandu::{Id, Node};
+///
+/// let tree = nandu::parse("And(a, b)").unwrap();
+/// assert!(matches!(tree, Node::Func { id: Id::And, .. }));
+/// ```
+pub fn parse(input: impl AsRef<str>) -> Result<Node> {
+    start(&mut lex(input.as_ref()).peekable())
+}
+
+// Same as `parse`, but with other options than the default ones.
+pub fn parse_with(
+    input: impl AsRef<str>,
+    options: ParseOptions,
+) -> Result<Node> {
+    start_with(&mut lex(input.as_ref()).peekable(), options)
+}
+
+// Parse `input` into its function definitions and the
+// expression which uses them, without translating it.
+pub fn parse_program(input: impl AsRef<str>) -> Result<Program> {
+    start_program(&mut lex(input.as_ref()).peekable())
+}
+
+// How long each phase of `parse_all_timed` took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseTimings {
+    pub lex:   Duration,
+    pub parse: Duration,
+}
+
+// Same as `parse_all`, but the input is lexed completely before
+// it's parsed, so that both phases can be timed.
+pub fn parse_all_timed(
+    input: impl AsRef<str>,
+) -> Result<(Vec<Node>, ParseTimings)> {
+    let start = Instant::now();
+    let tokens: Vec<_> = lex(input.as_ref()).collect();
+    let lexed = Instant::now();
+    let trees = start_multi(&mut tokens.into_iter().peekable())?;
+    let timings = ParseTimings {
+        lex:   lexed - start,
+        parse: lexed.elapsed(),
+    };
+    Ok((trees, timings))
+}
+
+// Parse each of the expressions in `input` separately,
+// without lowering them.
+pub fn parse_all(input: impl AsRef<str>) -> Result<Vec<Node>> {
+    start_multi(&mut lex(input.as_ref()).peekable())
+}
+
+// Parse each of the expressions in `input` separately and
+// lower them to `Nand`s.
+pub fn lower_all(input: impl AsRef<str>) -> Result<Vec<Node>> {
+    let mut trees = parse_all(input)?;
+    for ast in trees.iter_mut() {
+        ast.to_nand();
+    }
+    Ok(trees)
+}
+
+// Translate each of the expressions in `input` separately.
+pub fn translate_all(input: impl AsRef<str>) -> Result<Vec<String>> {
+    let trees = lower_all(input)?;
+    Ok(trees.iter().map(Node::to_string).collect())
+}
+
+// Same as `translate_all`, but every `Nand` is followed by a
+// `/* start..end */` comment with the byte range of the call
+// in `input` which it was lowered from.
+pub fn translate_annotated(input: impl AsRef<str>) -> Result<Vec<String>> {
+    let (trees, maps) =
+        start_multi_mapped(&mut lex(input.as_ref()).peekable())?;
+    Ok(trees
+        .iter()
+        .zip(&maps)
+        .map(|(tree, map)| tree.to_nand_annotated(map))
+        .collect())
+}
+
+// Why two expressions couldn't be compared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckError {
+    Parse(ParseError),
+    Eval(EvalError),
+    // With `EquivOptions::require_same_vars`, these variables
+    // only appear in one of the expressions.
+    DifferentVariables(Vec<String>),
+}
+
+impl std::fmt::Display for CheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Parse(e) => write!(f, "{e}"),
+            Self::Eval(e) => write!(f, "{e}"),
+            Self::DifferentVariables(vars) => {
+                let vars: Vec<String> =
+                    vars.iter().map(|var| format!("'{var}'")).collect();
+                write!(
+                    f,
+                    "only one of the expressions uses {}",
+                    vars.join(", ")
+                )
+            },
+        }
+    }
+}
+
+impl std::error::Error for CheckError {}
+
+impl From<ParseError> for CheckError {
+    fn from(e: ParseError) -> Self {
+        Self::Parse(e)
+    }
+}
+
+impl From<EvalError> for CheckError {
+    fn from(e: EvalError) -> Self {
+        Self::Eval(e)
+    }
+}
+
+// Check whether `a` and `b` have the same output for every
+// assignment of their variables. A variable which only appears
+// in one of them doesn't affect the other one, so for example
+// `Or(x, Not(x))` is equivalent to `1`, unless `equivalent_with`
+// requires the same variables. This takes time
+// exponential in the number of variables, so unless the trees
+// only differ in the order of arguments, it fails if there are
+// more than 20 of them.
+pub fn equivalent(
+    a: impl AsRef<str>,
+    b: impl AsRef<str>,
+) -> std::result::Result<bool, CheckError> {
+    equivalent_with(a, b, &EquivOptions::default())
+}
+
+// Settings for `equivalent_with`. The default settings compare
+// just like `equivalent` does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EquivOptions {
+    // Fail with `CheckError::DifferentVariables` instead of
+    // comparing expressions which don't use the same variables.
+    pub require_same_vars: bool,
+}
+
+pub fn equivalent_with(
+    a: impl AsRef<str>,
+    b: impl AsRef<str>,
+    options: &EquivOptions,
+) -> std::result::Result<bool, CheckError> {
+    let mut a = parse(a)?;
+    let mut b = parse(b)?;
+    if options.require_same_vars {
+        let (vars_a, vars_b) = (a.variables(), b.variables());
+        let different: Vec<String> = vars_a
+            .symmetric_difference(&vars_b)
+            .map(|&var| var.to_owned())
+            .collect();
+        if !different.is_empty() {
+            return Err(CheckError::DifferentVariables(different));
+        }
+    }
+    // Trees which only differ in the order of arguments of
+    // commutative functions are equivalent without trying
+    // every assignment.
+    a.canonicalize();
+    b.canonicalize();
+    Ok(a == b || same_truth_table(&a, &b)?)
+}
+
+// All assignments of the variables of `a` and `b` for which
+// they have different outputs. There are none exactly if they
+// are equivalent. Like `equivalent`, this takes time exponential
+// in the number of variables and fails if there are more than 20.
+pub fn mismatches(
+    a: impl AsRef<str>,
+    b: impl AsRef<str>,
+) -> std::result::Result<Vec<Mismatch>, CheckError> {
+    Ok(eval::mismatches(&parse(a)?, &parse(b)?)?)
+}
+
+// Check that lowering `expr` with `to_nand` doesn't change its
+// output for any assignment of its variables. This takes time
+// exponential in the number of variables and fails if there are
+// more than 20.
+pub fn check_lowering(expr: &Node) -> std::result::Result<bool, EvalError> {
+    check_lowering_by(expr, Node::to_nand)
+}
+
+// Same as `check_lowering`, but with `lower` in place of `to_nand`,
+// so that tests can check a lowering which is wrong.
+fn check_lowering_by(
+    expr: &Node,
+    lower: impl FnOnce(&mut Node),
+) -> std::result::Result<bool, EvalError> {
+    // Lowering wide functions can take long, so trees which are
+    // too big to check aren't lowered.
+    check_table_size(&expr.variables().into_iter().collect::<Vec<_>>())?;
+    let mut lowered = expr.clone();
+    lower(&mut lowered);
+    same_truth_table(expr, &lowered)
+}
+
+// Build an SMT-LIB script which an SMT solver reports as
+// unsatisfiable exactly if `a` and `b` are equivalent. Unlike
+// `equivalent`, this doesn't try every assignment itself.
+pub fn equivalence_query(
+    a: impl AsRef<str>,
+    b: impl AsRef<str>,
+) -> Result<String> {
+    let a = parse(a)?;
+    let b = parse(b)?;
+    Ok(smtlib::equivalence_query(&a, &b))
+}
+
+// Parse `input` and return all errors in it instead of
+// stopping at the first one. An empty list means that the
+// input can be translated.
+pub fn check(input: impl AsRef<str>) -> Vec<ParseError> {
+    let (_, errors) = start_collect(&mut lex(input.as_ref()).peekable());
+    errors
 }
 
 #[cfg(test)]
@@ -32,11 +439,338 @@ mod tests {
     use test::Bencher;
 
     use super::*;
+    use crate::lex::{Spanned, Token};
+
+    // Translate `input` and compare the result to `expected`.
+    // On a mismatch, both strings are printed on top of each
+    // other with a marker below the first differing character.
+    #[track_caller]
+    pub(crate) fn assert_nand_snapshot(input: &str, expected: &str) {
+        let actual = translate(input)
+            .unwrap_or_else(|e| panic!("failed to translate '{input}': {e}"));
+        if actual != expected {
+            let offset = actual
+                .chars()
+                .zip(expected.chars())
+                .take_while(|(a, e)| a == e)
+                .count();
+            panic!(
+                "snapshot mismatch for '{input}'\n\
+                 expected: {expected}\n  \
+                 actual: {actual}\n          \
+                 {:offset$}^",
+                ""
+            );
+        }
+    }
+
+    #[test]
+    fn translate_with_default_options_is_translate() {
+        let options = TranslateOptions::default();
+        for input in ["And(a, b)", "Not(Not(a))", "Xor(a, 1)"] {
+            assert_eq!(translate_with(input, &options), translate(input));
+        }
+    }
+
+    #[test]
+    fn translate_with_fold() {
+        let options = TranslateOptions {
+            fold: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            translate_with("And(a, Or(b, 1))", &options),
+            Ok("a".to_owned())
+        );
+        assert_eq!(translate_with("Nand(1, 1)", &options), Ok("0".to_owned()));
+    }
+
+    #[test]
+    fn translate_with_rejects_long_identifiers() {
+        let options = TranslateOptions {
+            lex: LexOptions {
+                max_ident_len: 4,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(translate_with("Not(abcd)", &options).is_ok());
+        let err = translate_with("Not(abcde)", &options).unwrap_err();
+        assert_eq!(err, ParseError::IdentTooLong {
+            limit: 4,
+            span:  Span { start: 4, end: 9 },
+        });
+        assert_eq!(err.to_string(), "identifier is longer than 4 bytes");
+    }
+
+    #[test]
+    fn translate_with_semicolon_delimiter() {
+        let options = TranslateOptions {
+            lex: LexOptions {
+                delimiter: ';',
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            translate_with("And(a; Not(b);)", &options),
+            translate("And(a, Not(b),)")
+        );
+        assert!(translate_with("And(a, b)", &options).is_err());
+        assert!(translate("And(a; b)").is_err());
+    }
+
+    #[test]
+    fn translate_with_unsupported_delimiter_fails() {
+        for delimiter in [' ', '\t', '&', '|', '#', 'x', '1', '_', '`', '('] {
+            let options = TranslateOptions {
+                lex: LexOptions {
+                    delimiter,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            assert_eq!(
+                translate_with("And(a, b)", &options),
+                Err(ParseError::InvalidDelimiter(delimiter))
+            );
+        }
+        assert_eq!(
+            ParseError::InvalidDelimiter(' ').to_string(),
+            "' ' can't be used as a delimiter"
+        );
+    }
+
+    #[test]
+    fn translate_with_simplify() {
+        let options = TranslateOptions {
+            simplify: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            translate("Not(Not(a))"),
+            Ok("Nand(Nand(a, a), Nand(a, a))".to_owned())
+        );
+        assert_eq!(translate_with("Not(Not(a))", &options), Ok("a".to_owned()));
+    }
+
+    #[test]
+    fn translate_with_pretty() {
+        let options = TranslateOptions {
+            pretty: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            translate_with("Not(a)", &options),
+            Ok("Nand(\n    a,\n    a\n)".to_owned())
+        );
+    }
+
+    #[test]
+    fn translate_nor() {
+        assert_nand_snapshot(
+            "Nor(a, b)",
+            "Nand(Nand(Nand(a, a), Nand(b, b)), Nand(Nand(a, a), Nand(b, b)))",
+        );
+    }
+
+    #[test]
+    fn translate_expands_definitions() {
+        assert_nand_snapshot(
+            "def Imply(a, b) = Or(Not(a), b)\nImply(x, y)",
+            "Nand(Nand(Nand(x, x), Nand(x, x)), Nand(y, y))",
+        );
+    }
+
+    #[test]
+    fn and_is_equivalent_to_its_translation() {
+        let nands = translate("And(a, b)").unwrap();
+        assert_eq!(equivalent("And(a, b)", &nands), Ok(true));
+        assert_eq!(equivalent("And(a, b)", "Or(a, b)"), Ok(false));
+    }
+
+    #[test]
+    fn equivalent_uses_variables_of_both_expressions() {
+        assert_eq!(equivalent("Or(x, Not(x))", "Or(1, 0)"), Ok(true));
+        assert_eq!(equivalent("And(a, b)", "And(a, c)"), Ok(false));
+        assert!(equivalent("And(a, b)", "Foo(a)").is_err());
+    }
+
+    #[test]
+    fn equivalent_with_same_variables_required() {
+        let options = EquivOptions {
+            require_same_vars: true,
+        };
+        let a = "And(a, b)";
+        let b = "And(a, Or(b, And(c, Not(c))))";
+        assert_eq!(equivalent(a, b), Ok(true));
+        let err = equivalent_with(a, b, &options).unwrap_err();
+        assert_eq!(err, CheckError::DifferentVariables(vec!["c".to_owned()]));
+        assert_eq!(err.to_string(), "only one of the expressions uses 'c'");
+        assert_eq!(equivalent_with(a, "And(b, a)", &options), Ok(true));
+        assert_eq!(equivalent_with(a, "Or(b, a)", &options), Ok(false));
+    }
+
+    fn stream(input: &str, options: &StreamOptions) -> io::Result<String> {
+        let mut output = vec![];
+        translate_stream_with(io::Cursor::new(input), &mut output, options)?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn translate_stream_writes_one_line_per_expression() {
+        let mut output = vec![];
+        let input = io::Cursor::new("Not(a)\n\n# Comment\nNand(a, 1)\n");
+        translate_stream(input, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Nand(a, a)\nNand(a, 1)\n"
+        );
+    }
+
+    #[test]
+    fn translate_stream_reports_errors_and_goes_on() {
+        let input = "Not(a)\nNot(a, b\nFoo(a)\nNand(a, b)";
+        assert_eq!(
+            stream(input, &StreamOptions::default()).unwrap(),
+            "Nand(a, a)\n\
+             Error at line 2, col 4: unbalanced parentheses: 1 opened, but 0 \
+             closed\n\
+             Error at line 3: unknown function id 'Foo'\n\
+             Nand(a, b)\n"
+        );
+    }
+
+    #[test]
+    fn translate_stream_accepts_any_line_break() {
+        let input = "Not(a)\r\nFoo(a)\rNand(a, b)\n\r\nNot(b, a\r\n";
+        assert_eq!(
+            stream(input, &StreamOptions::default()).unwrap(),
+            "Nand(a, a)\n\
+             Error at line 2: unknown function id 'Foo'\n\
+             Nand(a, b)\n\
+             Error at line 5, col 4: unbalanced parentheses: 1 opened, but 0 \
+             closed\n"
+        );
+    }
+
+    #[test]
+    fn line_translator_counts_every_line() {
+        let mut lines = LineTranslator::default();
+        assert_eq!(lines.translate("Not(a)\r# Comment\rFoo(a)"), [
+            Ok("Nand(a, a)".to_owned()),
+            Err("Error at line 3: unknown function id 'Foo'".to_owned()),
+        ]);
+        assert_eq!(lines.translate(""), []);
+        assert_eq!(lines.translate("Not(a"), [Err(
+            "Error at line 5, col 4: unbalanced parentheses: 1 opened, but 0 \
+             closed"
+                .to_owned()
+        )]);
+        assert_eq!(lines.lines(), 5);
+    }
+
+    #[test]
+    fn translate_stream_can_abort_on_error() {
+        let options = StreamOptions {
+            abort_on_error: true,
+            ..StreamOptions::default()
+        };
+        let err = stream("Not(a)\nFoo(a)\nNot(b)", &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "Error at line 2: unknown function id 'Foo'"
+        );
+    }
+
+    #[test]
+    fn equivalence_query_uses_both_expressions() {
+        let query = equivalence_query("And(a, b)", "Not(c)").unwrap();
+        assert!(query.contains("(declare-const |c| Bool)\n"));
+        assert!(query.contains("(assert (not (= (and |a| |b|) (not |c|))))\n"));
+    }
+
+    #[test]
+    fn equivalent_skips_assignments_for_commuted_arguments() {
+        // Trying all 2^40 assignments would take far too long.
+        let vars: Vec<String> = (0..40).map(|i| format!("v{i}")).collect();
+        let a = format!("And({})", vars.join(", "));
+        let reversed: Vec<&str> =
+            vars.iter().rev().map(String::as_str).collect();
+        let b = format!("And({})", reversed.join(", "));
+        assert_eq!(equivalent(&a, &b), Ok(true));
+    }
+
+    #[test]
+    fn equivalent_fails_with_too_many_variables() {
+        let vars: Vec<String> = (0..64).map(|i| format!("v{i}")).collect();
+        let a = format!("And({})", vars.join(", "));
+        let b = format!("Or({})", vars.join(", "));
+        let e = CheckError::Eval(EvalError::TooManyVariables {
+            count: 64,
+            limit: 20,
+        });
+        assert_eq!(equivalent(&a, &b), Err(e.clone()));
+        assert_eq!(mismatches(&a, &b), Err(e));
+        assert!(check_lowering(&parse(&a).unwrap()).is_err());
+    }
+
+    #[test]
+    fn check_lowering_accepts_builtin_lowerings() {
+        for input in ["And(a, b)", "Or(a, b, c)", "Xnor(Imp(a, b), Not(c))"] {
+            assert_eq!(
+                check_lowering(&parse(input).unwrap()),
+                Ok(true),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn check_lowering_detects_corrupted_trees() {
+        let tree = parse("Or(a, b)").unwrap();
+        assert_eq!(check_lowering_by(&tree, Node::to_nand), Ok(true));
+        // `Nand(Nand(a, a), Nand(b, b))` becomes `Nand(a, Nand(b, b))`.
+        let corrupted = |tree: &mut Node| {
+            tree.to_nand();
+            let Node::Func { args, .. } = tree else {
+                panic!("lowered tree is not a function");
+            };
+            args[0] = Node::Var { id: "a".to_owned() };
+        };
+        assert_eq!(check_lowering_by(&tree, corrupted), Ok(false));
+    }
+
+    #[test]
+    fn parse_all_timed_parses_like_parse_all() {
+        let input = "And(a, b)\nNot(a)";
+        let (trees, _) = parse_all_timed(input).unwrap();
+        assert_eq!(Ok(trees), parse_all(input));
+        assert_eq!(
+            parse_all_timed("And(a").unwrap_err(),
+            parse_all("And(a").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn translate_all_translates_every_expression() {
+        assert_eq!(
+            translate_all("Nand(a, b)\nNot(a)\n"),
+            Ok(vec!["Nand(a, b)".to_owned(), "Nand(a, a)".to_owned()])
+        );
+    }
+
+    #[test]
+    fn check_finds_all_errors() {
+        assert!(check("And(a, b)").is_empty());
+        assert_eq!(check("And(Foo(a), Or(a, ))").len(), 2);
+    }
 
     #[bench]
     fn bench_lots_of_nested_ands(b: &mut Bencher) {
         let ands = "And(a, b)\n";
-        let tokens: Vec<Token> = Token::lexer(ands).collect();
+        let tokens: Vec<Spanned<Token>> = lex(ands).collect();
         b.iter(|| {
             let mut ast =
                 start(&mut tokens.iter().cloned().peekable()).unwrap();
@@ -44,4 +778,129 @@ mod tests {
             ast.to_string();
         });
     }
+
+    // Lex `input` once, and then parse, lower and print it again
+    // and again.
+    fn bench_input(b: &mut Bencher, input: &str) {
+        let tokens: Vec<Spanned<Token>> = lex(input).collect();
+        b.iter(|| {
+            let mut ast =
+                start(&mut tokens.iter().cloned().peekable()).unwrap();
+            ast.to_nand();
+            ast.to_string()
+        });
+    }
+
+    // A single `And` with 12 arguments, folded into 11 nested ones.
+    #[bench]
+    fn bench_wide_and_input(b: &mut Bencher) {
+        let args: Vec<String> = (0..12).map(|i| format!("x{i}")).collect();
+        bench_input(b, &format!("And({})", args.join(", ")));
+    }
+
+    // A chain of 10 `Or`s, each nested in the next one.
+    #[bench]
+    fn bench_deep_or_chain(b: &mut Bencher) {
+        let mut input = "a".to_owned();
+        for i in 0..10 {
+            input = format!("Or({input}, x{i})");
+        }
+        bench_input(b, &input);
+    }
+
+    // A random tree of all the built-in functions.
+    #[bench]
+    fn bench_mixed_tree(b: &mut Bencher) {
+        let input = random_expr(1, 5, &["a", "b", "c", "d"]).to_string();
+        bench_input(b, &input);
+    }
+
+    // Lots of constants, which are kept as inputs of the gates.
+    #[bench]
+    fn bench_constants(b: &mut Bencher) {
+        let args: Vec<&str> = (0..8)
+            .map(|i| ["Xor(1, 0)", "Not(1)", "Nand(0, a)", "Imp(1, 1)"][i % 4])
+            .collect();
+        bench_input(b, &format!("Or({})", args.join(", ")));
+    }
+
+    // Same input as above, but the lowered tree is shared
+    // before it's used. Compare with `distinct_nodes` to see
+    // how many nodes the tree would need otherwise.
+    #[bench]
+    fn bench_lots_of_nested_ands_shared(b: &mut Bencher) {
+        let ands = "And(a, b)\n";
+        let tokens: Vec<Spanned<Token>> = lex(ands).collect();
+        b.iter(|| {
+            let mut ast =
+                start(&mut tokens.iter().cloned().peekable()).unwrap();
+            ast.to_nand();
+            ast.share().distinct_nodes()
+        });
+    }
+
+    // Lower a single `And` with 10,000 arguments. Lowering the tree
+    // with `to_nand` takes time exponential in the number of
+    // arguments, because each of them doubles the size of the
+    // lowered tree. In a release build, 20 arguments take 0.2s
+    // and 24 arguments 3.6s, so 10,000 never finish. Going straight
+    // to the DAG is linear instead, and all 10,000 arguments take
+    // about 11ms.
+    #[bench]
+    fn bench_wide_and(b: &mut Bencher) {
+        let args: Vec<Node> = (0..10_000)
+            .map(|i| Node::Var {
+                id: format!("x{i}"),
+            })
+            .collect();
+        let ast = Node::Func { id: Id::And, args };
+        assert_eq!(ast.to_nand_dag().count_gates(), 2 * 9_999);
+        b.iter(|| ast.to_nand_dag().count_gates());
+    }
+
+    // Minimize a chain of nested `And`s. The tree has twice as
+    // many gates for every `And`, but the minimized circuit only
+    // two more.
+    #[bench]
+    fn bench_minimize_nested_ands(b: &mut Bencher) {
+        let mut input = "a".to_owned();
+        for _ in 0..10 {
+            input = format!("And({input}, b)");
+        }
+        let ast = parse(&input).unwrap();
+        let mut lowered = ast.clone();
+        lowered.to_nand();
+        assert_eq!(lowered.count_gates(), 3 * ((1 << 10) - 1));
+        assert_eq!(ast.minimize().count_gates(), 2 * 10);
+        b.iter(|| ast.minimize().count_gates());
+    }
+
+    // Feed lots of random inputs to `translate` and check that it
+    // never panics. Most of the bytes are pieces of the grammar,
+    // so that the inputs get past the lexer. This takes a while,
+    // so it only runs with `cargo +nightly test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn translate_never_panics_on_random_input() {
+        const PIECES: [&str; 20] = [
+            "And(", "Or(", "Not(", "Xor(", "Nand(", "Fn(", "a", "b", "0", "1",
+            ", ", ")", "(", "&", "~", "let a = ", " in ", "def Fn(", ") = ",
+            "\n",
+        ];
+        let mut rng = crate::random::Rng(0);
+        for _ in 0..1_000_000 {
+            let mut bytes = vec![];
+            for _ in 0..rng.below(24) {
+                match rng.below(8) {
+                    0 => bytes.push(rng.next() as u8),
+                    _ => bytes.extend(PIECES[rng.below(PIECES.len())].bytes()),
+                }
+            }
+            let Ok(input) = String::from_utf8(bytes) else {
+                continue;
+            };
+            let result = std::panic::catch_unwind(|| translate(&input));
+            assert!(result.is_ok(), "translate panicked on {input:?}");
+        }
+    }
 }
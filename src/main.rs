@@ -1,55 +1,883 @@
-use std::env;
-use std::io::{self, BufRead};
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{env, fs};
 
 use atty::Stream;
 
 fn load_stdin() -> io::Result<String> {
-    if atty::is(Stream::Stdin) {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            "stdin is not redirected",
-        ));
-    }
-
-    let input = io::stdin()
-        .lock()
-        .lines()
-        .fold("".to_string(), |acc, line| {
-            acc + &line.expect("failed to read line from pipe") + "\n"
+    let mut input = String::new();
+    for line in io::stdin().lock().lines() {
+        input.push_str(&line?);
+        input.push('\n');
+    }
+    Ok(input)
+}
+
+// How translations are printed.
+#[derive(Default, Clone, Copy)]
+enum Format {
+    // The `Nand` expression itself.
+    #[default]
+    Nand,
+    // A Graphviz digraph of the circuit.
+    Dot,
+    // A Verilog module per expression.
+    Verilog,
+    // A BLIF model per expression.
+    Blif,
+    // A Python expression which uses `and`, `or` and `not`.
+    Python,
+    // The `Nand` expression, with shared gates bound by `let`s.
+    Shared,
+    // The tree as JSON.
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+impl Format {
+    const NAMES: [&'static str; 7] =
+        ["nand", "dot", "verilog", "blif", "python", "shared", "json"];
+
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "nand" => Ok(Self::Nand),
+            "dot" => Ok(Self::Dot),
+            "verilog" => Ok(Self::Verilog),
+            "blif" => Ok(Self::Blif),
+            "python" => Ok(Self::Python),
+            "shared" => Ok(Self::Shared),
+            #[cfg(feature = "serde")]
+            "json" => Ok(Self::Json),
+            #[cfg(not(feature = "serde"))]
+            "json" => Err("format 'json' needs the 'serde' feature".to_owned()),
+            _ => Err(format!(
+                "unknown format '{name}', expected one of: {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+// How the input is written.
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+enum InputFormat {
+    // Expressions in nandu's own syntax.
+    #[default]
+    Text,
+    // A tree as JSON, like `--format json` prints it.
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+impl InputFormat {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "text" => Ok(Self::Text),
+            #[cfg(feature = "serde")]
+            "json" => Ok(Self::Json),
+            #[cfg(not(feature = "serde"))]
+            "json" => {
+                Err("input format 'json' needs the 'serde' feature".to_owned())
+            },
+            _ => Err(format!(
+                "unknown input format '{name}', expected one of: text, json"
+            )),
+        }
+    }
+}
+
+// Settings taken from the command line.
+#[derive(Default)]
+struct Options {
+    // Print a summary of each circuit instead of the translation.
+    stats:       bool,
+    // Print the number of `Nand`s instead of the translation.
+    count:       bool,
+    // Print the number of levels of gates instead of the
+    // translation.
+    depth:       bool,
+    format:      Format,
+    format_in:   InputFormat,
+    // Print translations over multiple lines with indentation.
+    pretty:      bool,
+    input:       Option<String>,
+    // Arguments after the input. Only `equiv` uses them.
+    rest:        Vec<String>,
+    // Instead of translating the input, check whether it's
+    // equivalent to this expression.
+    check_equiv: Option<String>,
+    // With `check_equiv`, fail if the expressions don't use the
+    // same variables.
+    strict:      bool,
+    // Instead of translating the input, print where its
+    // translation differs from the one of this expression.
+    diff:        Option<String>,
+    // Same as `check_equiv`, but the other expression is read
+    // from this file, and mismatches are printed.
+    expected:    Option<PathBuf>,
+    // Read the input from these files, one after the other. This
+    // takes precedence over an input argument.
+    files:       Vec<PathBuf>,
+    // Translate stdin line by line and keep going after errors.
+    repl:        bool,
+    // Write the translations to this file instead of stdout.
+    output:      Option<PathBuf>,
+    // Print the tree before and after lowering it.
+    dump_ast:    bool,
+    // Print the parsed expressions without lowering them.
+    no_lower:    bool,
+    // With `no_lower`, print the input as it's written once it's
+    // known to be valid, instead of reformatting it.
+    preserve:    bool,
+    // Fold constants before lowering.
+    fold:        bool,
+    // Lower wide `And`s and `Or`s into balanced trees.
+    balanced:    bool,
+    // Warn about expressions whose output is always the same.
+    warn_const:  bool,
+    // Follow each `Nand` with the span of the input it came from.
+    annotate:    bool,
+    // Print how long each phase took to stderr.
+    time:        bool,
+    // Print the fanout of each signal instead of the translation.
+    fanout:      bool,
+    // Print the names of the inputs instead of the translation.
+    inputs:      bool,
+    // Print the tree after every step of lowering it.
+    explain:     bool,
+    // Print nothing, but fail if a translation has more gates.
+    max_gates:   Option<usize>,
+    // Gates which are kept instead of lowering them to `Nand`s.
+    gates:       nandu::GateSet,
+}
+
+fn parse_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<Options, String> {
+    let mut options = Options::default();
+    // The last of `--preserve` and `--canonical`, if any.
+    let mut layout = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stats" => options.stats = true,
+            "--count" => options.count = true,
+            "--depth" => options.depth = true,
+            "--pretty" => options.pretty = true,
+            "--repl" => options.repl = true,
+            "--dump-ast" => options.dump_ast = true,
+            "--no-lower" => options.no_lower = true,
+            "--preserve" | "--canonical" => {
+                options.preserve = arg == "--preserve";
+                layout = Some(arg);
+            },
+            "--fold" => options.fold = true,
+            "--balanced" => options.balanced = true,
+            "--warn-constant" => options.warn_const = true,
+            "--annotate" => options.annotate = true,
+            "--time" => options.time = true,
+            "--fanout" => options.fanout = true,
+            "--inputs" => options.inputs = true,
+            "--explain" => options.explain = true,
+            "--strict" => options.strict = true,
+            "--format" | "-F" => {
+                let name = args.next().ok_or("missing value for '--format'")?;
+                options.format = Format::parse(&name)?;
+            },
+            "--format-in" => {
+                let name =
+                    args.next().ok_or("missing value for '--format-in'")?;
+                options.format_in = InputFormat::parse(&name)?;
+            },
+            "--check-equiv" => {
+                let other =
+                    args.next().ok_or("missing value for '--check-equiv'")?;
+                options.check_equiv = Some(other);
+            },
+            "--diff" => {
+                let other = args.next().ok_or("missing value for '--diff'")?;
+                options.diff = Some(other);
+            },
+            "--expected" => {
+                let path =
+                    args.next().ok_or("missing value for '--expected'")?;
+                options.expected = Some(path.into());
+            },
+            "--file" | "-f" => {
+                let path = args.next().ok_or("missing value for '--file'")?;
+                options.files.push(path.into());
+            },
+            "--target-gates" => {
+                let names =
+                    args.next().ok_or("missing value for '--target-gates'")?;
+                options.gates = parse_gates(&names)?;
+            },
+            "--max-gates" => {
+                let max =
+                    args.next().ok_or("missing value for '--max-gates'")?;
+                let max = max
+                    .parse()
+                    .map_err(|_| format!("invalid number of gates '{max}'"))?;
+                options.max_gates = Some(max);
+            },
+            "--output" | "-o" => {
+                let path = args.next().ok_or("missing value for '--output'")?;
+                options.output = Some(path.into());
+            },
+            _ if arg.starts_with('-') => {
+                return Err(format!("unknown option '{arg}'"));
+            },
+            _ if options.input.is_some() => options.rest.push(arg),
+            _ => options.input = Some(arg),
+        }
+    }
+    // Translations are always canonical.
+    if let Some(layout) = layout {
+        if !options.no_lower {
+            return Err(format!(
+                "'{layout}' can only be used with '--no-lower'"
+            ));
+        }
+    }
+    // These don't go through the phases which `--time` measures.
+    let untimed = [
+        ("--dump-ast", options.dump_ast),
+        ("--explain", options.explain),
+        ("--annotate", options.annotate),
+    ];
+    if let Some((other, _)) = untimed.iter().find(|(_, set)| *set) {
+        if options.time {
+            return Err(format!("'--time' can't be used with '{other}'"));
+        }
+    }
+    Ok(options)
+}
+
+// Read a list of gates like `and,or,not`. The names are the
+// ones of the functions, but in any case.
+fn parse_gates(names: &str) -> Result<nandu::GateSet, String> {
+    names
+        .split(',')
+        .map(|name| {
+            nandu::Id::all()
+                .iter()
+                .find(|(id_name, ..)| id_name.eq_ignore_ascii_case(name.trim()))
+                .map(|&(_, id, _)| id)
+                .ok_or_else(|| format!("unknown gate '{}'", name.trim()))
+        })
+        .collect()
+}
+
+// Translate each line of stdin as soon as it's read, until the
+// end of the input. Errors are printed, but don't stop the loop.
+fn repl(pretty: bool) {
+    let interactive = atty::is(Stream::Stdin);
+    let mut translator = nandu::LineTranslator::new(nandu::TranslateOptions {
+        pretty,
+        ..Default::default()
+    });
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        if interactive {
+            print!("> ");
+            io::stdout().flush().expect("failed to write prompt");
+        }
+        let Some(line) = lines.next() else {
+            break;
+        };
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                let i = translator.lines() + 1;
+                eprintln!("Error at line {i}: failed to read it: {e}");
+                std::process::exit(1);
+            },
+        };
+        for result in translator.translate(&line) {
+            match result {
+                Ok(nand) => println!("{nand}"),
+                Err(message) => eprintln!("{message}"),
+            }
+        }
+    }
+}
+
+// Write the size of a lowered tree as labeled lines, or as
+// JSON if that's the chosen format.
+fn write_stats(
+    out: &mut dyn Write,
+    tree: &nandu::Node,
+    format: Format,
+) -> io::Result<()> {
+    let stats = tree.stats();
+    match format {
+        #[cfg(feature = "serde")]
+        Format::Json => {
+            let json = serde_json::to_string_pretty(&stats)
+                .expect("stats can always be serialized");
+            writeln!(out, "{json}")
+        },
+        _ => {
+            writeln!(out, "gates: {}", stats.gate_count)?;
+            writeln!(out, "inputs: {}", stats.var_count)?;
+            writeln!(out, "depth: {}", stats.depth)
+        },
+    }
+}
+
+// Write how many gate inputs each signal of the shared circuit
+// feeds, one signal per line. Inputs come first, then gates.
+// The netlist only has `Nand`s, so `tree` is lowered first if it
+// isn't already, like with `--no-lower` or `--target-gates`.
+fn write_fanout(out: &mut dyn Write, tree: &nandu::Node) -> io::Result<()> {
+    let mut tree = tree.clone();
+    tree.to_nand();
+    let netlist = tree.share().to_netlist();
+    let fanout = netlist.fanout();
+    let signals = netlist
+        .inputs()
+        .iter()
+        .map(|id| nandu::Signal::Input(id.clone()))
+        .chain([nandu::Signal::Const(false), nandu::Signal::Const(true)])
+        .chain(netlist.gate_ids().map(nandu::Signal::Gate));
+    for signal in signals {
+        let Some(n) = fanout.get(&signal) else {
+            continue;
+        };
+        match &signal {
+            nandu::Signal::Input(id) => writeln!(out, "{id}: {n}")?,
+            nandu::Signal::Const(value) => {
+                writeln!(out, "{}: {n}", u8::from(*value))?
+            },
+            nandu::Signal::Gate(id) => {
+                writeln!(out, "gate {}: {n}", id.index())?
+            },
+        }
+    }
+    Ok(())
+}
+
+// Write each of the lowered trees in the way `options` asks for.
+fn write_trees(
+    out: &mut dyn Write,
+    trees: Vec<nandu::Node>,
+    options: &Options,
+) -> io::Result<()> {
+    let num_trees = trees.len();
+    for (i, tree) in trees.into_iter().enumerate() {
+        if options.stats {
+            write_stats(out, &tree, options.format)?;
+            continue;
+        }
+        if options.fanout {
+            write_fanout(out, &tree)?;
+            continue;
+        }
+        if options.inputs {
+            let vars: Vec<&str> = tree.variables().into_iter().collect();
+            writeln!(out, "{}", vars.join(", "))?;
+            continue;
+        }
+        if options.count {
+            writeln!(out, "{}", tree.count_gates())?;
+        }
+        if options.depth {
+            writeln!(out, "{}", tree.depth())?;
+        }
+        if options.count || options.depth {
+            continue;
+        }
+        // Modules need distinct names if there are
+        // multiple of them.
+        let name = if num_trees == 1 {
+            "circuit".to_owned()
+        } else {
+            format!("circuit_{i}")
+        };
+        match options.format {
+            Format::Nand if options.pretty => writeln!(out, "{tree:#}")?,
+            Format::Nand => writeln!(out, "{tree}")?,
+            Format::Dot => writeln!(out, "{}", tree.to_dot())?,
+            Format::Verilog => writeln!(out, "{}", tree.to_verilog(&name))?,
+            Format::Blif => writeln!(out, "{}", tree.to_blif(&name))?,
+            Format::Python => writeln!(out, "{}", tree.to_python())?,
+            Format::Shared => writeln!(out, "{}", tree.share())?,
+            #[cfg(feature = "serde")]
+            Format::Json => {
+                let json = serde_json::to_string_pretty(&tree)
+                    .expect("trees can always be serialized");
+                writeln!(out, "{json}")?;
+            },
+        }
+    }
+    Ok(())
+}
+
+// Write the parsed tree and the same tree after lowering it.
+fn write_dump(
+    out: &mut dyn Write,
+    mut tree: nandu::Node,
+    pretty: bool,
+) -> io::Result<()> {
+    writeln!(out, "Parsed:")?;
+    if pretty {
+        writeln!(out, "{tree:#?}")?;
+    } else {
+        writeln!(out, "{tree:?}")?;
+    }
+    tree.to_nand();
+    writeln!(out, "Lowered:")?;
+    if pretty {
+        writeln!(out, "{tree:#?}")
+    } else {
+        writeln!(out, "{tree:?}")
+    }
+}
+
+// How long each phase of a translation took, summed over
+// all inputs.
+#[derive(Default)]
+struct Timings {
+    lex:    Duration,
+    parse:  Duration,
+    lower:  Duration,
+    format: Duration,
+}
+
+impl Timings {
+    fn print(&self) {
+        eprintln!("lex: {:?}", self.lex);
+        eprintln!("parse: {:?}", self.parse);
+        eprintln!("lower: {:?}", self.lower);
+        eprintln!("format: {:?}", self.format);
+    }
+}
+
+// Write the tree as it's parsed and again after each step of
+// lowering it, one line per step.
+fn write_steps(out: &mut dyn Write, mut tree: nandu::Node) -> io::Result<()> {
+    writeln!(out, "{tree}")?;
+    while tree.lower_step() {
+        writeln!(out, "{tree}")?;
+    }
+    Ok(())
+}
+
+// Where the results go: the file given with `--output`, or stdout.
+// This is only called once the input is known to be valid, so
+// errors don't truncate the file.
+fn open_output(path: Option<&Path>) -> Box<dyn Write> {
+    match path {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(io::BufWriter::new(file)),
+            Err(e) => {
+                eprintln!("Error: failed to create '{}': {e}", path.display());
+                std::process::exit(1);
+            },
+        },
+        None => Box::new(io::stdout().lock()),
+    }
+}
+
+// Report an error in `input` and exit. If the input was read
+// from a file, the error names it.
+fn exit_with_error(
+    path: Option<&Path>,
+    input: &str,
+    e: nandu::ParseError,
+) -> ! {
+    let place = match path {
+        Some(path) => format!(" in '{}'", path.display()),
+        None => String::new(),
+    };
+    match e.span() {
+        Some(span) => {
+            let (line, col) = nandu::line_col(input, span.start);
+            eprint!("{}", nandu::caret_lines(input, span));
+            eprintln!("Error{place} at line {line}, col {col}: {e}");
+        },
+        None => eprintln!("Error{place}: {e}"),
+    }
+    std::process::exit(1);
+}
+
+// Parse all expressions in `input`, or read the tree in it if
+// it's JSON. Inputs which start with `{` are always JSON, since
+// that can't start an expression.
+fn parse_input(
+    path: Option<&Path>,
+    input: &str,
+    format: InputFormat,
+) -> (Vec<nandu::Node>, nandu::ParseTimings) {
+    #[cfg(feature = "serde")]
+    if format == InputFormat::Json || input.trim_start().starts_with('{') {
+        let start = Instant::now();
+        let tree = nandu::Node::from_json(input).unwrap_or_else(|e| {
+            match path {
+                Some(path) => eprintln!("Error in '{}': {e}", path.display()),
+                None => eprintln!("Error: {e}"),
+            }
+            std::process::exit(1);
         });
+        let timings = nandu::ParseTimings {
+            parse: start.elapsed(),
+            ..Default::default()
+        };
+        return (vec![tree], timings);
+    }
+    #[cfg(not(feature = "serde"))]
+    let _ = format;
+    nandu::parse_all_timed(input)
+        .unwrap_or_else(|e| exit_with_error(path, input, e))
+}
 
-    Ok(input)
+// What to do with the input. Without a subcommand, it's
+// translated.
+enum Command {
+    Translate,
+    // Same as `translate --stats`.
+    Stats,
+    // Check whether the input is equivalent to the expression
+    // after it. Same as `translate --check-equiv`.
+    Equiv,
+    // Check whether the input is equivalent to the one in the
+    // file given with `--expected`, and print where they differ.
+    AssertEquiv,
+}
+
+// Settings of the `gen` subcommand.
+struct GenOptions {
+    seed:  u64,
+    depth: usize,
+}
+
+fn parse_gen_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<GenOptions, String> {
+    let mut options = GenOptions { seed: 0, depth: 4 };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => {
+                let seed = args.next().ok_or("missing value for '--seed'")?;
+                options.seed = seed
+                    .parse()
+                    .map_err(|_| format!("invalid seed '{seed}'"))?;
+            },
+            "--depth" => {
+                let depth = args.next().ok_or("missing value for '--depth'")?;
+                options.depth = depth
+                    .parse()
+                    .map_err(|_| format!("invalid depth '{depth}'"))?;
+            },
+            _ => return Err(format!("unknown option '{arg}'")),
+        }
+    }
+    Ok(options)
+}
+
+// Print a random expression instead of translating one. Trees
+// which are nested deeper than the parser allows can't be read
+// again, so the depth is limited to that.
+fn gen(args: impl Iterator<Item = String>) {
+    let options = match parse_gen_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        },
+    };
+    let depth = options.depth.min(nandu::ParseOptions::default().max_depth);
+    let tree = nandu::random_expr(options.seed, depth, &["a", "b", "c", "d"]);
+    println!("{tree}");
 }
 
 fn main() {
     env_logger::init();
 
-    let mut args = env::args();
+    let mut args = env::args().peekable();
     args.next().unwrap(); // Ignore own name.
 
-    let input = match args.next() {
-        Some(input) => {
-            log::info!("Input from argument:\n'{input}'");
-            input
+    // Without a subcommand, the arguments are the same as those
+    // of `translate`, so that `nandu "And(a, b)"` keeps working.
+    let command = match args.peek().map(String::as_str) {
+        Some("gen") => {
+            args.next();
+            gen(args);
+            return;
+        },
+        Some("translate") => Some(Command::Translate),
+        Some("stats") => Some(Command::Stats),
+        Some("equiv") => Some(Command::Equiv),
+        Some("assert-equiv") => Some(Command::AssertEquiv),
+        _ => None,
+    };
+    if command.is_some() {
+        args.next();
+    }
+
+    let mut options = match parse_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
         },
-        None => match load_stdin() {
+    };
+    match command.unwrap_or(Command::Translate) {
+        Command::Translate => {},
+        Command::Stats => options.stats = true,
+        Command::Equiv => {
+            if options.rest.is_empty() {
+                eprintln!("Error: missing expression to compare with");
+                std::process::exit(1);
+            }
+            options.check_equiv = Some(options.rest.remove(0));
+        },
+        Command::AssertEquiv if options.expected.is_none() => {
+            eprintln!("Error: missing '--expected' file to compare with");
+            std::process::exit(1);
+        },
+        Command::AssertEquiv => {},
+    }
+    if options.strict && options.check_equiv.is_none() {
+        eprintln!("Error: '--strict' can only be used with '--check-equiv'");
+        std::process::exit(1);
+    }
+
+    // Without any other input, a terminal on stdin starts the
+    // REPL too.
+    let no_input = options.files.is_empty() && options.input.is_none();
+    if options.repl || (no_input && atty::is(Stream::Stdin)) {
+        repl(options.pretty);
+        return;
+    }
+
+    // Each input is a file, or the argument or stdin without a path.
+    let inputs: Vec<(Option<PathBuf>, String)> = if !options.files.is_empty() {
+        std::mem::take(&mut options.files)
+            .into_iter()
+            .map(|path| match fs::read_to_string(&path) {
+                Ok(input) => {
+                    log::info!(
+                        "Input from file {}:\n'{input}'",
+                        path.display()
+                    );
+                    (Some(path), input)
+                },
+                Err(e) => {
+                    eprintln!(
+                        "Error: failed to read '{}': {e}",
+                        path.display()
+                    );
+                    std::process::exit(1);
+                },
+            })
+            .collect()
+    } else if let Some(input) = options.input.take() {
+        log::info!("Input from argument:\n'{input}'");
+        vec![(None, input)]
+    } else {
+        match load_stdin() {
             Ok(input) => {
                 log::info!("Input from stdin pipe:\n'{input}'");
-                input
+                vec![(None, input)]
             },
             Err(e) => {
-                log::warn!("Aborting because of pipe error: {e}");
+                eprintln!("Error: failed to read stdin: {e}");
                 std::process::exit(1);
             },
-        },
+        }
     };
 
-    let result = nandu::translate(input);
-    match result {
-        Ok(translation) => println!("{translation}"),
-        Err(e) => {
-            eprintln!("Error: {e}");
+    if let Some(path) = &options.expected {
+        let expected = fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("Error: failed to read '{}': {e}", path.display());
             std::process::exit(1);
-        },
+        });
+        for (_, input) in &inputs {
+            match nandu::mismatches(input, &expected) {
+                Ok(mismatches) if mismatches.is_empty() => {
+                    println!("equivalent")
+                },
+                Ok(mismatches) => {
+                    println!(
+                        "not equivalent, the input vs. the expected output:"
+                    );
+                    for mismatch in mismatches {
+                        println!("{mismatch}");
+                    }
+                    std::process::exit(1);
+                },
+                Err(e) => {
+                    eprintln!("Error: {e}");
+                    std::process::exit(1);
+                },
+            }
+        }
+        return;
+    }
+
+    if let Some(other) = &options.diff {
+        let mut other = nandu::parse(other)
+            .unwrap_or_else(|e| exit_with_error(None, other, e));
+        if !options.no_lower {
+            other.to_nand();
+        }
+        let mut differ = false;
+        for (path, input) in &inputs {
+            let (trees, _) =
+                parse_input(path.as_deref(), input, options.format_in);
+            for mut tree in trees {
+                if !options.no_lower {
+                    tree.to_nand();
+                }
+                for entry in tree.structural_diff(&other) {
+                    println!("{entry}");
+                    differ = true;
+                }
+            }
+        }
+        if differ {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(other) = &options.check_equiv {
+        let equiv_options = nandu::EquivOptions {
+            require_same_vars: options.strict,
+        };
+        for (_, input) in &inputs {
+            match nandu::equivalent_with(input, other, &equiv_options) {
+                Ok(true) => println!("equivalent"),
+                Ok(false) => {
+                    println!("not equivalent");
+                    std::process::exit(1);
+                },
+                Err(e) => {
+                    eprintln!("Error: {e}");
+                    std::process::exit(1);
+                },
+            }
+        }
+        return;
+    }
+
+    let written = if options.dump_ast {
+        let trees: Vec<nandu::Node> = inputs
+            .iter()
+            .map(|(path, input)| {
+                nandu::parse(input).unwrap_or_else(|e| {
+                    exit_with_error(path.as_deref(), input, e)
+                })
+            })
+            .collect();
+        let mut out = open_output(options.output.as_deref());
+        trees
+            .into_iter()
+            .try_for_each(|tree| write_dump(&mut out, tree, options.pretty))
+            .and_then(|()| out.flush())
+    } else if options.explain {
+        let mut trees = vec![];
+        for (path, input) in &inputs {
+            let (parsed, _) =
+                parse_input(path.as_deref(), input, options.format_in);
+            trees.extend(parsed);
+        }
+        let mut out = open_output(options.output.as_deref());
+        trees
+            .into_iter()
+            .try_for_each(|tree| write_steps(&mut out, tree))
+            .and_then(|()| out.flush())
+    } else if options.annotate {
+        let mut nands = vec![];
+        for (path, input) in &inputs {
+            let annotated = nandu::translate_annotated(input)
+                .unwrap_or_else(|e| exit_with_error(path.as_deref(), input, e));
+            nands.extend(annotated);
+        }
+        let mut out = open_output(options.output.as_deref());
+        nands
+            .iter()
+            .try_for_each(|nand| writeln!(out, "{nand}"))
+            .and_then(|()| out.flush())
+    } else if options.no_lower && options.preserve {
+        for (path, input) in &inputs {
+            parse_input(path.as_deref(), input, options.format_in);
+        }
+        let mut out = open_output(options.output.as_deref());
+        inputs
+            .iter()
+            .try_for_each(|(_, input)| writeln!(out, "{}", input.trim_end()))
+            .and_then(|()| out.flush())
+    } else {
+        let mut timings = Timings::default();
+        let mut trees = vec![];
+        for (path, input) in &inputs {
+            let (parsed, parse_timings) =
+                parse_input(path.as_deref(), input, options.format_in);
+            timings.lex += parse_timings.lex;
+            timings.parse += parse_timings.parse;
+            trees.extend(parsed);
+        }
+        if options.warn_const {
+            for tree in &trees {
+                match tree.constant_value() {
+                    Ok(Some(value)) => eprintln!(
+                        "Warning: '{tree}' is always {}",
+                        u8::from(value)
+                    ),
+                    Ok(None) => {},
+                    Err(e) => eprintln!(
+                        "Note: can't tell whether '{tree}' is constant: {e}"
+                    ),
+                }
+            }
+        }
+        if options.fold {
+            for tree in trees.iter_mut() {
+                tree.fold();
+            }
+        }
+        if !options.no_lower {
+            let start = Instant::now();
+            for tree in trees.iter_mut() {
+                if options.balanced {
+                    tree.balance_variadic();
+                }
+                tree.to_gates(&options.gates);
+            }
+            timings.lower = start.elapsed();
+        }
+        if let Some(max) = options.max_gates {
+            let mut too_large = false;
+            for tree in &trees {
+                let count = tree.count_gates();
+                if count > max {
+                    eprintln!(
+                        "Error: circuit has {count} gates, but at most {max} \
+                         are allowed"
+                    );
+                    too_large = true;
+                }
+            }
+            if too_large {
+                std::process::exit(1);
+            }
+            return;
+        }
+        let mut out = open_output(options.output.as_deref());
+        let start = Instant::now();
+        let written =
+            write_trees(&mut out, trees, &options).and_then(|()| out.flush());
+        timings.format = start.elapsed();
+        if options.time {
+            timings.print();
+        }
+        written
     };
+    if let Err(e) = written {
+        eprintln!("Error: failed to write output: {e}");
+        std::process::exit(1);
+    }
 }
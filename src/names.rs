@@ -0,0 +1,73 @@
+use std::collections::{HashMap, HashSet};
+
+// Names for the variables in `vars` in some output format. Each
+// variable for which `is_valid` holds keeps its name. The others
+// get a name which only has ASCII letters, digits and underscores
+// and starts with a letter, with as many underscores at the end
+// as it takes for `is_valid` to hold and for no two variables to
+// get the same name.
+pub(crate) fn output_names<'a>(
+    vars: &[&'a str],
+    is_valid: impl Fn(&str) -> bool,
+) -> HashMap<&'a str, String> {
+    let mut names: HashMap<&str, String> = vars
+        .iter()
+        .filter(|var| is_valid(var))
+        .map(|&var| (var, var.to_owned()))
+        .collect();
+    let mut taken: HashSet<String> = names.values().cloned().collect();
+    for &var in vars {
+        if names.contains_key(var) {
+            continue;
+        }
+        let mut name: String = var
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            name.insert(0, 'v');
+        }
+        while !is_valid(&name) || taken.contains(&name) {
+            name.push('_');
+        }
+        taken.insert(name.clone());
+        names.insert(var, name);
+    }
+    names
+}
+
+// Whether `id` is made of ASCII letters, digits and underscores,
+// and doesn't start with a digit.
+pub(crate) fn is_simple(id: &str) -> bool {
+    let mut chars = id.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_names_are_kept() {
+        let names = output_names(&["a", "b_1"], is_simple);
+        assert_eq!(names["a"], "a");
+        assert_eq!(names["b_1"], "b_1");
+    }
+
+    #[test]
+    fn invalid_names_are_replaced_by_unused_ones() {
+        let names = output_names(&["my var", "my_var", "1x", "ä"], is_simple);
+        assert_eq!(names["my_var"], "my_var");
+        assert_eq!(names["my var"], "my_var_");
+        assert_eq!(names["1x"], "v1x");
+        assert_eq!(names["ä"], "v_");
+
+        let names =
+            output_names(&["if", "if_"], |id| is_simple(id) && id != "if");
+        assert_eq!(names["if"], "if__");
+        assert_eq!(names["if_"], "if_");
+    }
+}
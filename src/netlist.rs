@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+
+use crate::dag::{Dag, DagNode};
+use crate::parse::Id;
+use crate::tree::Node;
+
+// Position of a gate in a `Netlist`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GateId(usize);
+
+impl GateId {
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+// Where the value on a wire comes from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Signal {
+    Gate(GateId),
+    Input(String),
+    Const(bool),
+}
+
+// A `Nand` gate and the signals on its two inputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gate {
+    pub inputs: [Signal; 2],
+}
+
+// A circuit made up of only `Nand` gates. Gates are numbered in
+// the order they are needed, so the inputs of a gate are always
+// driven by gates with a lower index, by primary inputs or by
+// constants.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Netlist {
+    gates:  Vec<Gate>,
+    inputs: Vec<String>,
+    output: Signal,
+}
+
+impl Netlist {
+    fn new() -> Self {
+        Self {
+            gates:  vec![],
+            inputs: vec![],
+            output: Signal::Const(false),
+        }
+    }
+
+    fn push_gate(&mut self, inputs: Vec<Signal>) -> Signal {
+        let Ok(inputs) = <[Signal; 2]>::try_from(inputs) else {
+            panic!("a `Nand` must have two arguments after lowering");
+        };
+        self.gates.push(Gate { inputs });
+        Signal::Gate(GateId(self.gates.len() - 1))
+    }
+
+    fn input(&mut self, id: &str) -> Signal {
+        if !self.inputs.iter().any(|input| input == id) {
+            self.inputs.push(id.to_owned());
+        }
+        Signal::Input(id.to_owned())
+    }
+
+    pub fn gates(&self) -> &[Gate] {
+        &self.gates
+    }
+
+    pub fn gate(&self, id: GateId) -> &Gate {
+        &self.gates[id.0]
+    }
+
+    // Ids of all gates in order.
+    pub fn gate_ids(&self) -> impl Iterator<Item = GateId> {
+        (0..self.gates.len()).map(GateId)
+    }
+
+    // Names of the primary inputs in the order they first
+    // appear in the expression.
+    pub fn inputs(&self) -> &[String] {
+        &self.inputs
+    }
+
+    // Number of gate inputs which each signal is connected to. A
+    // gate which gets the same signal on both inputs counts twice.
+    // The output of the circuit isn't counted, and signals which
+    // don't feed any gate are left out.
+    pub fn fanout(&self) -> HashMap<Signal, usize> {
+        let mut fanout = HashMap::new();
+        for gate in &self.gates {
+            for input in &gate.inputs {
+                *fanout.entry(input.clone()).or_insert(0) += 1;
+            }
+        }
+        fanout
+    }
+
+    // The signal which carries the value of the whole circuit.
+    // This is a gate unless the expression was only a variable
+    // or a constant.
+    pub fn output(&self) -> &Signal {
+        &self.output
+    }
+}
+
+impl Node {
+    // Build a netlist with one gate for each `Nand` in the tree,
+    // numbered in post-order. Identical sub-trees get separate
+    // gates; use `share` first to build each of them only once.
+    // The expression is lowered first if it isn't already.
+    pub fn to_netlist(&self) -> Netlist {
+        fn visit(node: &Node, netlist: &mut Netlist) -> Signal {
+            match node {
+                Node::Func { id, args } => {
+                    debug_assert_eq!(*id, Id::Nand);
+                    let inputs =
+                        args.iter().map(|arg| visit(arg, netlist)).collect();
+                    netlist.push_gate(inputs)
+                },
+                Node::Var { id } => netlist.input(id),
+                Node::Const { value } => Signal::Const(*value),
+            }
+        }
+
+        let mut tree = self.clone();
+        tree.to_nand();
+
+        let mut netlist = Netlist::new();
+        netlist.output = visit(&tree, &mut netlist);
+        netlist
+    }
+}
+
+impl Dag {
+    // Build a netlist with one gate for each distinct `Nand` in
+    // the DAG. The DAG must already be lowered.
+    pub fn to_netlist(&self) -> Netlist {
+        let mut netlist = Netlist::new();
+        // Arguments come before the nodes which use them, so the
+        // signal of every argument is known by the time it's used.
+        let mut signals: Vec<Signal> = vec![];
+        for node in self.nodes() {
+            let signal = match node {
+                DagNode::Func { id, args } => {
+                    debug_assert_eq!(*id, Id::Nand);
+                    let inputs = args
+                        .iter()
+                        .map(|arg| signals[arg.index()].clone())
+                        .collect();
+                    netlist.push_gate(inputs)
+                },
+                DagNode::Var { id } => netlist.input(id),
+                DagNode::Const { value } => Signal::Const(*value),
+            };
+            signals.push(signal);
+        }
+        netlist.output = signals[self.root().index()].clone();
+        netlist
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::lex;
+    use crate::parse::start;
+
+    fn parse(input: &str) -> Node {
+        start(&mut lex(input).peekable()).unwrap()
+    }
+
+    fn gate(a: Signal, b: Signal) -> Gate {
+        Gate { inputs: [a, b] }
+    }
+
+    fn input(id: &str) -> Signal {
+        Signal::Input(id.to_owned())
+    }
+
+    #[test]
+    fn netlist_of_and_has_three_gates() {
+        let netlist = parse("And(a, b)").to_netlist();
+        assert_eq!(netlist.inputs(), ["a", "b"]);
+        assert_eq!(netlist.gates(), [
+            gate(input("a"), input("b")),
+            gate(input("a"), input("b")),
+            gate(Signal::Gate(GateId(0)), Signal::Gate(GateId(1))),
+        ]);
+        assert_eq!(netlist.output(), &Signal::Gate(GateId(2)));
+    }
+
+    #[test]
+    fn netlist_of_dag_shares_gates() {
+        let mut tree = parse("And(a, b)");
+        tree.to_nand();
+        let netlist = tree.share().to_netlist();
+        assert_eq!(netlist.gates(), [
+            gate(input("a"), input("b")),
+            gate(Signal::Gate(GateId(0)), Signal::Gate(GateId(0))),
+        ]);
+        assert_eq!(netlist.output(), &Signal::Gate(GateId(1)));
+    }
+
+    #[test]
+    fn netlist_inputs_are_listed_once_in_order() {
+        let netlist = parse("Nand(b, Nand(a, b))").to_netlist();
+        assert_eq!(netlist.inputs(), ["b", "a"]);
+        assert_eq!(netlist.gate(GateId(0)), &gate(input("a"), input("b")));
+    }
+
+    #[test]
+    fn fanout_of_minimized_and() {
+        let netlist = parse("And(a, b)").minimize().to_netlist();
+        let fanout = netlist.fanout();
+        assert_eq!(fanout[&input("a")], 1);
+        assert_eq!(fanout[&input("b")], 1);
+        // The shared inner `Nand` drives both inputs of the outer one.
+        assert_eq!(fanout[&Signal::Gate(GateId(0))], 2);
+        assert!(!fanout.contains_key(&Signal::Gate(GateId(1))));
+    }
+
+    #[test]
+    fn fanout_without_sharing() {
+        let fanout = parse("And(a, b)").to_netlist().fanout();
+        assert_eq!(fanout[&input("a")], 2);
+        assert_eq!(fanout[&input("b")], 2);
+        assert_eq!(fanout[&Signal::Gate(GateId(0))], 1);
+        assert_eq!(fanout[&Signal::Gate(GateId(1))], 1);
+        assert_eq!(parse("Nand(1, 1)").to_netlist().fanout(), {
+            HashMap::from([(Signal::Const(true), 2)])
+        });
+    }
+
+    #[test]
+    fn netlist_without_gates() {
+        let netlist = parse("a").to_netlist();
+        assert!(netlist.gates().is_empty());
+        assert_eq!(netlist.output(), &input("a"));
+        let netlist = parse("Nand(1, 0)").to_netlist();
+        assert_eq!(netlist.gates(), [gate(
+            Signal::Const(true),
+            Signal::Const(false)
+        )]);
+    }
+}
@@ -1,125 +1,833 @@
+use std::collections::HashSet;
 use std::iter::Peekable;
 
-use crate::lex::Token;
+use crate::annotate::SourceMap;
+use crate::diagnostic::edit_distance;
+use crate::lex::{Span, Spanned, Token};
+use crate::program::{Def, Program};
 use crate::tree::Node;
 use crate::Result;
 
 pub const AND_ID: &str = "And";
 pub const OR_ID: &str = "Or";
 pub const NAND_ID: &str = "Nand";
+pub const NOT_ID: &str = "Not";
+pub const XOR_ID: &str = "Xor";
+pub const NOR_ID: &str = "Nor";
+pub const XNOR_ID: &str = "Xnor";
+pub const IMP_ID: &str = "Imp";
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+// All built-in functions with their name and the number of
+// arguments they take. Everything which needs to know the set
+// of ids should use this list through `Id::all`.
+const ALL_IDS: [(&str, Id, usize); 8] = [
+    (AND_ID, Id::And, 2),
+    (OR_ID, Id::Or, 2),
+    (NAND_ID, Id::Nand, 2),
+    (NOT_ID, Id::Not, 1),
+    (XOR_ID, Id::Xor, 2),
+    (NOR_ID, Id::Nor, 2),
+    (XNOR_ID, Id::Xnor, 2),
+    (IMP_ID, Id::Imp, 2),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Id {
     And,
     Or,
     Nand,
+    Not,
+    Xor,
+    Nor,
+    Xnor,
+    // Implication, which is only false if the first argument
+    // is true and the second one is false.
+    Imp,
 }
 
 impl Id {
-    // NOTE: Custom function can be implemented later by
-    // allowing user-defined function identifiers here.
+    // Name, id and number of arguments of every built-in function.
+    pub fn all() -> &'static [(&'static str, Id, usize)] {
+        &ALL_IDS
+    }
+
+    fn entry(self) -> &'static (&'static str, Id, usize) {
+        Self::all()
+            .iter()
+            .find(|(_, id, _)| *id == self)
+            .expect("every id is in the list of all ids")
+    }
+
+    // The number of arguments the function takes. Variadic
+    // functions take at least this many.
+    pub fn arity(self) -> usize {
+        self.entry().2
+    }
+
+    // Whether the function takes any number of arguments, as
+    // long as there are at least `arity` of them.
+    pub fn is_variadic(self) -> bool {
+        matches!(self, Self::And | Self::Or)
+    }
+
+    // Only the built-in functions are known here. Calls to
+    // user-defined functions are resolved by the parser.
+    // The case of the id doesn't matter, but all of it must
+    // match, so `AND` is `And`, but `Andx` is unknown.
     pub fn parse(id: &str, num_args: usize) -> Option<Self> {
-        match id {
-            AND_ID if num_args == 2 => Some(Self::And),
-            OR_ID if num_args == 2 => Some(Self::Or),
-            NAND_ID if num_args == 2 => Some(Self::Nand),
-            _ => None,
+        let (_, known, _) = Self::all()
+            .iter()
+            .find(|(name, ..)| id.eq_ignore_ascii_case(name))?;
+        known.accepts(num_args).then_some(*known)
+    }
+
+    // Whether the function can be called with `num_args` arguments.
+    pub fn accepts(self, num_args: usize) -> bool {
+        let arity = self.arity();
+        num_args == arity || (self.is_variadic() && num_args > arity)
+    }
+
+    // The name of the known id which is closest to `id`, as long
+    // as it's only a few typos away. Nothing is suggested for a
+    // known id, which is only invalid if the number of arguments
+    // is wrong.
+    pub fn suggest(id: &str) -> Option<&'static str> {
+        let id = id.to_ascii_lowercase();
+        let distances = Self::all().iter().map(|(name, ..)| {
+            (*name, edit_distance(&id, &name.to_ascii_lowercase()))
+        });
+        if distances.clone().any(|(_, distance)| distance == 0) {
+            return None;
         }
+        distances
+            .filter(|(name, distance)| *distance <= name.len() / 2)
+            .min_by_key(|(_, distance)| *distance)
+            .map(|(name, _)| name)
     }
 }
 
 impl std::fmt::Display for Id {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            Self::And => write!(f, "{AND_ID}"),
-            Self::Or => write!(f, "{OR_ID}"),
-            Self::Nand => write!(f, "{NAND_ID}"),
-        }
+        write!(f, "{}", self.entry().0)
     }
 }
 
 // Parser subroutine to either consume the
 // expected token or throw an error.
 macro_rules! expect {
-    ($expected:pat, $lex:expr) => {
-        if let $expected = $lex.peek() {
-            consume($lex)
+    ($expected:pat, $parser:expr) => {
+        if let $expected = $parser.peek() {
+            $parser.consume()
         } else {
-            Err(ParseError::UnexpectedToken($lex.next()))
+            Err($parser.unexpected())
         }
     };
 }
 
+// Settings which change how the parser behaves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    // Maximum number of functions and `let`s which may be
+    // nested in each other. This keeps the parser's stack
+    // from overflowing on deep inputs.
+    pub max_depth: usize,
+    // Maximum number of arguments of a single function, including
+    // chains of the same infix operator like `a & b & c`. This
+    // keeps wide inputs from building huge trees.
+    pub max_args:  usize,
+    // Reject `let` bindings which are never used. Otherwise
+    // their value is simply dropped.
+    pub strict:    bool,
+}
+
+impl Default for ParseOptions {
+    // Each level takes a few kilobytes of stack in debug
+    // builds, so the default must still fit into the 2 MiB
+    // stacks which spawned threads get.
+    fn default() -> Self {
+        Self {
+            max_depth: 256,
+            max_args:  1 << 16,
+            strict:    false,
+        }
+    }
+}
+
 // Start symbol.
-// Rule: `<S> ::= <F> end`.
+// Rule: `<S> ::= <D>* <E> end`.
 // `end` means that the input is over, so in
 // this case that `lex.peek` is `None`.
-pub fn start(lex: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Node> {
-    let tree = func(lex)?;
-    if lex.peek().is_none() {
-        consume(lex).err();
-    } else {
-        return Err(ParseError::UnexpectedToken(lex.next()));
-    }
-    Ok(tree)
+pub fn start(
+    lex: &mut Peekable<impl Iterator<Item = Spanned<Token>>>,
+) -> Result<Node> {
+    Ok(Parser::new(lex).start()?.main)
 }
 
-// Rule: `<F> ::= FuncIdent LParen <ArgList> RParen`
-fn func(lex: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Node> {
-    let token = expect!(Some(Token::FuncIdent(_)), lex)?;
-    expect!(Some(Token::LParen), lex)?;
-    let args = arg_list(lex)?;
-    expect!(Some(Token::RParen), lex)?;
-    let token_id = token.as_ref();
-    let id = Id::parse(token_id, args.len())
-        .ok_or(ParseError::InvalidFunctionId(token_id.to_owned()))?;
-    Ok(Node::Func { id, args })
+// Same as `start`, but keeps the function definitions.
+pub fn start_program(
+    lex: &mut Peekable<impl Iterator<Item = Spanned<Token>>>,
+) -> Result<Program> {
+    Parser::new(lex).start()
 }
 
-// Rule: `<Arg> (Delim <Arg>)*`
-// In the AST this function is not represented as a node
-// on its own. Instead this function returns all arguments
-// as a list of branches.
-fn arg_list(
-    lex: &mut Peekable<impl Iterator<Item = Token>>,
+// Same as `start`, but with other options than the default ones.
+pub fn start_with(
+    lex: &mut Peekable<impl Iterator<Item = Spanned<Token>>>,
+    options: ParseOptions,
+) -> Result<Node> {
+    let mut parser = Parser::new(lex);
+    parser.options = options;
+    Ok(parser.start()?.main)
+}
+
+// Start symbol for inputs with multiple expressions.
+// Rule: `<M> ::= <D>* <E> (<D>* <E>)* end`.
+// The expressions simply follow each other. Commonly
+// they are put on separate lines.
+pub fn start_multi(
+    lex: &mut Peekable<impl Iterator<Item = Spanned<Token>>>,
 ) -> Result<Vec<Node>> {
-    let mut args = vec![];
-    args.push(arg(lex)?);
-    while let Some(Token::Delim) = lex.peek() {
-        consume(lex)?;
-        args.push(arg(lex)?);
-    }
-    Ok(args)
+    Parser::new(lex).start_multi()
 }
 
-// Rule: `VarIdent | <F>`
-fn arg(lex: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Node> {
-    if let Some(Token::VarIdent(_)) = lex.peek() {
-        let token = consume(lex)?;
-        let node = Node::Var {
-            id: token.as_ref().to_owned(),
-        };
-        Ok(node)
-    } else if let Some(Token::FuncIdent(_)) = lex.peek() {
-        func(lex)
+// Same as `start_multi`, but also remembers where in the input
+// each function call was. There is one map for each tree.
+pub fn start_multi_mapped(
+    lex: &mut Peekable<impl Iterator<Item = Spanned<Token>>>,
+) -> Result<(Vec<Node>, Vec<SourceMap>)> {
+    let mut parser = Parser::new(lex);
+    parser.maps = Some(vec![]);
+    let trees = parser.start_multi()?;
+    let maps = parser.maps.unwrap_or_default();
+    debug_assert_eq!(trees.len(), maps.len());
+    Ok((trees, maps))
+}
+
+// Same as `start`, but instead of stopping at the first error,
+// the parser tries to recover and keeps going to find as many
+// errors as possible. The tree is only returned if there were
+// no errors at all.
+pub fn start_collect(
+    lex: &mut Peekable<impl Iterator<Item = Spanned<Token>>>,
+) -> (Option<Node>, Vec<ParseError>) {
+    let mut parser = Parser::new(lex);
+    parser.errors = Some(vec![]);
+    let tree = match parser.start() {
+        Ok(program) => Some(program.main),
+        Err(e) => {
+            parser.record(e);
+            None
+        },
+    };
+    let errors = parser.errors.unwrap_or_default();
+    if errors.is_empty() {
+        (tree, errors)
     } else {
-        Err(ParseError::UnexpectedToken(lex.next()))
+        (None, errors)
     }
 }
 
-// Consume the current lookahead and advance the token
-// stream. Returns the consumed token or returns `None`
-// if the token stream has ended.
-#[inline]
-fn consume(lex: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Token> {
-    lex.next().ok_or(ParseError::UnexpectedEnd)
+// Stands in for an argument which couldn't be parsed during
+// error recovery, so that the number of arguments is still
+// right and the function doesn't report another error. It
+// never ends up in a returned tree.
+const PLACEHOLDER: Node = Node::Const { value: false };
+
+struct Parser<'l, I: Iterator<Item = Spanned<Token>>> {
+    lex:         &'l mut Peekable<I>,
+    // `Some` if the parser should recover from errors. In
+    // that case it contains all errors found so far.
+    errors:      Option<Vec<ParseError>>,
+    // Bindings which are in scope, innermost last, with the map
+    // of the bound value and whether each of them was used so far.
+    bindings:    Vec<(String, Node, SourceMap, bool)>,
+    // Names of all bindings in the current expression and
+    // of all variables which didn't refer to any binding.
+    bound_names: HashSet<String>,
+    free_names:  Vec<String>,
+    // User-defined functions seen so far.
+    defs:        Vec<Def>,
+    options:     ParseOptions,
+    // Number of functions and `let`s which are currently open.
+    depth:       usize,
+    // Spans of all consumed `(` which aren't closed yet, and
+    // the number of `)` consumed so far.
+    open_parens: Vec<Span>,
+    closed:      usize,
+    // `Some` if the parser should remember the span of each
+    // function it builds. Every node which is parsed pushes its
+    // map, and functions pop the maps of their arguments again.
+    // The end of the last consumed token is where the current
+    // function ends.
+    maps:        Option<Vec<SourceMap>>,
+    last_end:    usize,
+}
+
+impl<'l, I: Iterator<Item = Spanned<Token>>> Parser<'l, I> {
+    fn new(lex: &'l mut Peekable<I>) -> Self {
+        Self {
+            lex,
+            errors: None,
+            bindings: vec![],
+            bound_names: HashSet::new(),
+            free_names: vec![],
+            defs: vec![],
+            options: ParseOptions::default(),
+            depth: 0,
+            open_parens: vec![],
+            closed: 0,
+            maps: None,
+            last_end: 0,
+        }
+    }
+
+    fn start(&mut self) -> Result<Program> {
+        self.require_input()?;
+        let main = self.top_level()?;
+        // All of the input must be part of the expression.
+        if self.lex.peek().is_some() {
+            return Err(self.unexpected());
+        }
+        let defs = std::mem::take(&mut self.defs);
+        Ok(Program { defs, main })
+    }
+
+    fn start_multi(&mut self) -> Result<Vec<Node>> {
+        self.require_input()?;
+        let mut trees = vec![self.top_level()?];
+        while self.lex.peek().is_some() {
+            trees.push(self.top_level()?);
+        }
+        Ok(trees)
+    }
+
+    // Inputs without any tokens, like empty inputs or inputs
+    // with only comments, don't have an expression to parse.
+    fn require_input(&mut self) -> Result<()> {
+        match self.lex.peek() {
+            Some(_) => Ok(()),
+            None => Err(ParseError::EmptyInput),
+        }
+    }
+
+    // Rule: `<D>* <E>`
+    // Definitions stay in scope for all later expressions.
+    fn top_level(&mut self) -> Result<Node> {
+        while let Some(Token::Def) = self.peek() {
+            let def = self.def()?;
+            self.defs.push(def);
+        }
+        self.checked_expr()
+    }
+
+    // Rule: `<D> ::= Def FuncIdent LParen <ParamList> RParen Eq <E>`
+    // The parameters are bound to variables of the same
+    // name while the body is parsed. Any other variable in
+    // the body is an error.
+    fn def(&mut self) -> Result<Def> {
+        expect!(Some(Token::Def), self)?;
+        let name = expect!(Some(Token::FuncIdent(_)), self)?;
+        let name = name.inner.as_ref().to_owned();
+        expect!(Some(Token::LParen), self)?;
+        let params = self.param_list()?;
+        expect!(Some(Token::RParen), self)?;
+        expect!(Some(Token::Eq), self)?;
+        for param in &params {
+            let var = Node::Var { id: param.clone() };
+            self.bindings.push((
+                param.clone(),
+                var,
+                SourceMap::default(),
+                false,
+            ));
+        }
+        let body = self.checked_expr();
+        self.bindings.clear();
+        let body = body?;
+        // The body only shows up where the function is called.
+        self.pop_maps(1);
+        if let Some(name) = self.free_names.first() {
+            return Err(ParseError::UndefinedBinding(name.clone()));
+        }
+        Ok(Def { name, params, body })
+    }
+
+    // Rule: `<ParamList> ::= VarIdent (Delim VarIdent)*`
+    fn param_list(&mut self) -> Result<Vec<String>> {
+        let mut params = vec![];
+        loop {
+            let param = expect!(Some(Token::VarIdent(_)), self)?;
+            params.push(param.inner.as_ref().to_owned());
+            if let Some(Token::Delim) = self.peek() {
+                self.consume()?;
+            } else {
+                break;
+            }
+        }
+        Ok(params)
+    }
+
+    // Parse a complete expression. Variables which aren't
+    // bound are inputs of the expression. But if a binding
+    // of the same name exists elsewhere, the variable was
+    // most likely meant to refer to it.
+    fn checked_expr(&mut self) -> Result<Node> {
+        self.bound_names.clear();
+        self.free_names.clear();
+        let tree = self.expr()?;
+        match self
+            .free_names
+            .iter()
+            .find(|name| self.bound_names.contains(*name))
+        {
+            Some(name) => Err(ParseError::UndefinedBinding(name.clone())),
+            None => Ok(tree),
+        }
+    }
+
+    // Rule: `<E> ::= <L> | <Arg>`
+    fn expr(&mut self) -> Result<Node> {
+        if let Some(Token::Let) = self.peek() {
+            self.let_binding()
+        } else {
+            self.arg()
+        }
+    }
+
+    // Rule: `<L> ::= Let VarIdent Eq <Arg> In <E>`
+    // The bound value is substituted for each use of the
+    // name in `<E>`, so the binding itself doesn't show up
+    // in the AST.
+    fn let_binding(&mut self) -> Result<Node> {
+        self.nested(Self::let_binding_inner)
+    }
+
+    fn let_binding_inner(&mut self) -> Result<Node> {
+        expect!(Some(Token::Let), self)?;
+        let name = expect!(Some(Token::VarIdent(_)), self)?;
+        let name = name.inner.as_ref().to_owned();
+        expect!(Some(Token::Eq), self)?;
+        let value = self.arg()?;
+        let map = self.pop_maps(1).pop().unwrap_or_default();
+        expect!(Some(Token::In), self)?;
+        self.bound_names.insert(name.clone());
+        self.bindings.push((name, value, map, false));
+        let body = self.expr();
+        let (name, _, _, used) = self.bindings.pop().unwrap();
+        if self.options.strict && !used && body.is_ok() {
+            return self.recover(ParseError::UnusedBinding(name));
+        }
+        body
+    }
+
+    // Rule: `<F> ::= FuncIdent LParen <ArgList> RParen`
+    // User-defined functions take precedence over the
+    // built-in ones and are expanded right away.
+    fn func(&mut self) -> Result<Node> {
+        self.nested(Self::func_inner)
+    }
+
+    fn func_inner(&mut self) -> Result<Node> {
+        let token = expect!(Some(Token::FuncIdent(_)), self)?;
+        expect!(Some(Token::LParen), self)?;
+        let args = self.arg_list()?;
+        expect!(Some(Token::RParen), self)?;
+        let arg_maps = self.pop_maps(args.len());
+        let span = self.span_from(token.span.start);
+        let token_id = token.inner.as_ref();
+        if let Some(def) = self.defs.iter().rev().find(|d| d.name == token_id) {
+            if def.params.len() != args.len() {
+                return self.recover(ParseError::ArityMismatch {
+                    name:     token_id.to_owned(),
+                    expected: def.params.len(),
+                    found:    args.len(),
+                });
+            }
+            let node = def.expand(&args);
+            if self.maps.is_some() {
+                let map = SourceMap::expanded(def, span, &arg_maps);
+                self.push_map(map);
+            }
+            return Ok(node);
+        }
+        match Id::parse(token_id, args.len()) {
+            Some(id) => {
+                self.push_map(SourceMap::func(span, arg_maps));
+                Ok(Node::Func { id, args })
+            },
+            None => {
+                self.recover(ParseError::InvalidFunctionId(token_id.to_owned()))
+            },
+        }
+    }
+
+    // Rule: `<Arg> (Delim <Arg>)* Delim?`
+    // In the AST this function is not represented as a node
+    // on its own. Instead this function returns all arguments
+    // as a list of branches.
+    fn arg_list(&mut self) -> Result<Vec<Node>> {
+        let mut args = vec![];
+        args.push(self.arg_or_recover()?);
+        loop {
+            match self.peek() {
+                Some(Token::Delim) => {
+                    self.consume()?;
+                    // A single trailing delimiter is allowed.
+                    if let Some(Token::RParen) = self.peek() {
+                        break;
+                    }
+                    self.check_args(args.len() + 1)?;
+                    args.push(self.arg_or_recover()?);
+                },
+                Some(Token::RParen) | None => break,
+                // Anything else can't follow an argument.
+                Some(_) => {
+                    let e = self.unexpected();
+                    self.recover(e)?;
+                },
+            }
+        }
+        Ok(args)
+    }
+
+    fn arg_or_recover(&mut self) -> Result<Node> {
+        match self.arg() {
+            Ok(arg) => Ok(arg),
+            Err(e) => self.recover(e),
+        }
+    }
+
+    // Rule: `<Arg> ::= <Not> (<Op> <Not>)*`
+    // The infix operators bind from weakest to strongest in
+    // the order `|`, `^`, `&` and `~`.
+    fn arg(&mut self) -> Result<Node> {
+        self.infix(0)
+    }
+
+    // Precedence climbing over the binary operators. Only
+    // operators which bind at least as strong as `min_precedence`
+    // are part of the result. `a & b & c` becomes a single
+    // `And(a, b, c)`, just like the variadic call. `Xor` and
+    // `Nand` only take two arguments, so they're nested to the
+    // left instead. `Nand` binds as strong as `And`.
+    fn infix(&mut self, min_precedence: u8) -> Result<Node> {
+        let start = self.peek_start();
+        let mut lhs = self.not_operand()?;
+        // The function which `lhs` was built into by this loop.
+        let mut chain = None;
+        loop {
+            let (id, precedence) = match self.peek() {
+                Some(Token::OrOp) => (Id::Or, 1),
+                Some(Token::XorOp) => (Id::Xor, 2),
+                Some(Token::AndOp) => (Id::And, 3),
+                Some(Token::NandOp) => (Id::Nand, 3),
+                _ => break,
+            };
+            if precedence < min_precedence {
+                break;
+            }
+            self.consume()?;
+            let rhs = self.infix(precedence + 1)?;
+            let extend = chain == Some(id) && id.is_variadic();
+            let span = self.span_from(start);
+            let map = match <[SourceMap; 2]>::try_from(self.pop_maps(2)) {
+                Ok([mut lhs_map, rhs_map]) if extend => {
+                    lhs_map.push_arg(rhs_map);
+                    lhs_map.set_span(span);
+                    lhs_map
+                },
+                Ok(maps) => SourceMap::func(span, maps.into()),
+                Err(_) => SourceMap::default(),
+            };
+            lhs = match lhs {
+                Node::Func { mut args, .. } if extend => {
+                    self.check_args(args.len() + 1)?;
+                    args.push(rhs);
+                    Node::Func { id, args }
+                },
+                lhs => Node::Func {
+                    id,
+                    args: vec![lhs, rhs],
+                },
+            };
+            self.push_map(map);
+            chain = Some(id);
+        }
+        Ok(lhs)
+    }
+
+    // Rule: `<Not> ::= NotOp <Not> | <Primary>`
+    fn not_operand(&mut self) -> Result<Node> {
+        if let Some(Token::NotOp) = self.peek() {
+            self.nested(|parser| {
+                let start = parser.consume()?.span.start;
+                let arg = parser.not_operand()?;
+                let arg_maps = parser.pop_maps(1);
+                let span = parser.span_from(start);
+                parser.push_map(SourceMap::func(span, arg_maps));
+                Ok(Node::Func {
+                    id:   Id::Not,
+                    args: vec![arg],
+                })
+            })
+        } else {
+            self.primary()
+        }
+    }
+
+    // Rule: `<Primary> ::= VarIdent | Const | <F> | LParen <Arg> RParen`
+    fn primary(&mut self) -> Result<Node> {
+        if let Some(Token::VarIdent(_)) = self.peek() {
+            let token = self.consume()?;
+            let id = token.inner.as_ref();
+            let binding =
+                self.bindings.iter_mut().rev().find(|(name, ..)| name == id);
+            match binding {
+                Some((_, value, map, used)) => {
+                    *used = true;
+                    let (value, map) = (value.clone(), map.clone());
+                    self.push_map(map);
+                    Ok(value)
+                },
+                None => {
+                    self.free_names.push(id.to_owned());
+                    self.push_map(SourceMap::default());
+                    Ok(Node::Var { id: id.to_owned() })
+                },
+            }
+        } else if let Some(&Token::Const(value)) = self.peek() {
+            self.consume()?;
+            self.push_map(SourceMap::default());
+            Ok(Node::Const { value })
+        } else if let Some(Token::FuncIdent(_)) = self.peek() {
+            self.func()
+        } else if let Some(Token::LParen) = self.peek() {
+            self.nested(|parser| {
+                parser.consume()?;
+                let arg = parser.arg()?;
+                expect!(Some(Token::RParen), parser)?;
+                Ok(arg)
+            })
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    // Fail if a function would have `num_args` arguments, but
+    // that's more than the maximum.
+    fn check_args(&self, num_args: usize) -> Result<()> {
+        let limit = self.options.max_args;
+        if num_args > limit {
+            return Err(ParseError::TooManyArgs { limit });
+        }
+        Ok(())
+    }
+
+    // Run `rule` one level deeper, unless that exceeds the
+    // maximum depth.
+    fn nested(
+        &mut self,
+        rule: impl FnOnce(&mut Self) -> Result<Node>,
+    ) -> Result<Node> {
+        if self.depth >= self.options.max_depth {
+            return Err(ParseError::MaxDepthExceeded);
+        }
+        self.depth += 1;
+        let result = rule(self);
+        self.depth -= 1;
+        result
+    }
+
+    // Return the error if the parser doesn't recover from errors.
+    // Otherwise, record it, skip to a position where parsing can
+    // continue and pretend that a node was parsed.
+    fn recover(&mut self, e: ParseError) -> Result<Node> {
+        if self.errors.is_none() {
+            return Err(e);
+        }
+        // After an unknown function id or a wrong number of
+        // arguments, the parser already is behind the function,
+        // but unexpected tokens are left in the stream and
+        // skipped here.
+        let skip = !matches!(
+            e,
+            ParseError::InvalidFunctionId(_)
+                | ParseError::ArityMismatch { .. }
+                | ParseError::UnusedBinding(_)
+        );
+        self.record(e);
+        if skip {
+            self.skip_arg();
+        }
+        Ok(PLACEHOLDER)
+    }
+
+    fn record(&mut self, e: ParseError) {
+        if let Some(errors) = &mut self.errors {
+            // An unexpected end of input is reported by every
+            // function which is still open. Once is enough.
+            if errors.last() != Some(&e) {
+                errors.push(e);
+            }
+        }
+    }
+
+    // Skip the rest of the current argument. This stops in
+    // front of the next `Delim` or the `RParen` which closes
+    // the argument list. Nested parentheses are skipped.
+    fn skip_arg(&mut self) {
+        let mut depth = 0usize;
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Delim | Token::RParen if depth == 0 => break,
+                Token::LParen => depth += 1,
+                Token::RParen => depth -= 1,
+                _ => {},
+            }
+            let _ = self.consume();
+        }
+    }
+
+    // The current lookahead is not what the grammar allows.
+    // It's left in the token stream for error recovery. If
+    // the input ends before all `(` are closed or the lookahead
+    // is a `)` without a matching `(`, the parentheses are
+    // unbalanced, which is reported instead.
+    fn unexpected(&mut self) -> ParseError {
+        let opened = self.closed + self.open_parens.len();
+        match self.lex.peek() {
+            None if !self.open_parens.is_empty() => {
+                ParseError::UnbalancedParens {
+                    opened,
+                    closed: self.closed,
+                    span: *self.open_parens.last().unwrap(),
+                }
+            },
+            Some(token)
+                if token.inner == Token::RParen
+                    && self.open_parens.is_empty() =>
+            {
+                ParseError::UnbalancedParens {
+                    opened,
+                    closed: self.closed + 1,
+                    span: token.span,
+                }
+            },
+            Some(Spanned {
+                inner: Token::IdentTooLong(limit),
+                span,
+            }) => ParseError::IdentTooLong {
+                limit: *limit,
+                span:  *span,
+            },
+            token => ParseError::UnexpectedToken(token.cloned()),
+        }
+    }
+
+    // The input from `start` up to the last consumed token.
+    fn span_from(&self, start: usize) -> Span {
+        Span {
+            start,
+            end: self.last_end,
+        }
+    }
+
+    // Remember the map of the node which was just parsed.
+    fn push_map(&mut self, map: SourceMap) {
+        if let Some(maps) = &mut self.maps {
+            maps.push(map);
+        }
+    }
+
+    // Take the maps of the last `n` nodes which were parsed,
+    // first one first. There are none if the parser doesn't
+    // remember spans.
+    fn pop_maps(&mut self, n: usize) -> Vec<SourceMap> {
+        match &mut self.maps {
+            Some(maps) => maps.split_off(maps.len() - n),
+            None => vec![],
+        }
+    }
+
+    // Where the current lookahead starts, or the end of the
+    // input if there is none.
+    fn peek_start(&mut self) -> usize {
+        match self.lex.peek() {
+            Some(token) => token.span.start,
+            None => self.last_end,
+        }
+    }
+
+    // Look at the current lookahead without its span.
+    #[inline]
+    fn peek(&mut self) -> Option<&Token> {
+        self.lex.peek().map(|token| &token.inner)
+    }
+
+    // Consume the current lookahead and advance the token
+    // stream. Returns the consumed token or returns `None`
+    // if the token stream has ended.
+    #[inline]
+    fn consume(&mut self) -> Result<Spanned<Token>> {
+        let token = self.lex.next().ok_or(ParseError::UnexpectedEnd)?;
+        self.last_end = token.span.end;
+        match token.inner {
+            Token::LParen => self.open_parens.push(token.span),
+            Token::RParen => {
+                self.open_parens.pop();
+                self.closed += 1;
+            },
+            _ => {},
+        }
+        Ok(token)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ParseError {
-    UnexpectedToken(Option<Token>),
+    UnexpectedToken(Option<Spanned<Token>>),
+    // An identifier is longer than `LexOptions::max_ident_len`.
+    IdentTooLong {
+        limit: usize,
+        span:  Span,
+    },
     InvalidFunctionId(String),
+    UndefinedBinding(String),
+    // Only reported in strict mode.
+    UnusedBinding(String),
+    ArityMismatch {
+        name:     String,
+        expected: usize,
+        found:    usize,
+    },
+    MaxDepthExceeded,
+    TooManyArgs {
+        limit: usize,
+    },
+    // `span` is the innermost unclosed `(` if there are too few
+    // `)`, and the first extra `)` if there are too many.
+    UnbalancedParens {
+        opened: usize,
+        closed: usize,
+        span:   Span,
+    },
     UnexpectedEnd,
+    EmptyInput,
+    // `LexOptions::delimiter` can't separate arguments.
+    InvalidDelimiter(char),
+}
+
+impl ParseError {
+    // Position of the input where the error occurred, if known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::UnexpectedToken(Some(token)) => Some(token.span),
+            Self::IdentTooLong { span, .. } => Some(*span),
+            Self::UnbalancedParens { span, .. } => Some(*span),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for ParseError {
@@ -129,12 +837,47 @@ impl std::fmt::Display for ParseError {
                 Some(t) => write!(f, "unexpected token {t}"),
                 None => write!(f, "unexpected missing token"),
             },
+            Self::IdentTooLong { limit, .. } => {
+                write!(f, "identifier is longer than {limit} bytes")
+            },
             Self::InvalidFunctionId(id) => {
-                write!(f, "unknown function id '{id}'")
+                write!(f, "unknown function id '{id}'")?;
+                if let Some(suggestion) = Id::suggest(id) {
+                    write!(f, ", did you mean '{suggestion}'?")?;
+                }
+                Ok(())
+            },
+            Self::UndefinedBinding(name) => {
+                write!(f, "binding '{name}' is used outside of its scope")
+            },
+            Self::UnusedBinding(name) => {
+                write!(f, "binding '{name}' is never used")
+            },
+            Self::ArityMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "function '{name}' takes {expected} argument(s), but got {found}"
+            ),
+            Self::UnbalancedParens { opened, closed, .. } => write!(
+                f,
+                "unbalanced parentheses: {opened} opened, but {closed} closed"
+            ),
+            Self::InvalidDelimiter(c) => {
+                write!(f, "'{}' can't be used as a delimiter", c.escape_debug())
+            },
+            Self::MaxDepthExceeded => {
+                write!(f, "expression is nested too deeply")
+            },
+            Self::TooManyArgs { limit } => {
+                write!(f, "function has more than {limit} arguments")
             },
             Self::UnexpectedEnd => {
                 write!(f, "unexpected end of input")
             },
+            Self::EmptyInput => write!(f, "the input has no expression"),
         }
     }
 }
@@ -144,6 +887,603 @@ impl std::error::Error for ParseError {}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lex::{lex, unspanned};
+
+    fn func(
+        lex: &mut Peekable<impl Iterator<Item = Spanned<Token>>>,
+    ) -> Result<Node> {
+        Parser::new(lex).func()
+    }
+
+    fn collect(input: &str) -> (Option<Node>, Vec<ParseError>) {
+        start_collect(&mut lex(input).peekable())
+    }
+
+    #[test]
+    fn collect_returns_tree_without_errors() {
+        let (tree, errors) = collect("And(a, Or(b, c))");
+        assert_eq!(tree, start(&mut lex("And(a, Or(b, c))").peekable()).ok());
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn collect_reports_two_separate_mistakes() {
+        let (tree, errors) = collect("And(Foo(a), Or(a, ,))");
+        assert_eq!(tree, None);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0], ParseError::InvalidFunctionId("Foo".to_owned()));
+        assert_eq!(
+            errors[1],
+            ParseError::UnexpectedToken(Some(Spanned {
+                inner: Token::Delim,
+                span:  Span {
+                    start: 18,
+                    end:   19,
+                },
+            }))
+        );
+    }
+
+    #[test]
+    fn collect_recovers_inside_argument_list() {
+        let (_, errors) = collect("And(a, Nand(, b) c, Bar(d), Or(e,, f))");
+        let expected = ["','", "variable 'c'", "function id 'Bar'", "','"];
+        assert_eq!(errors.len(), expected.len(), "{errors:?}");
+        for (error, expected) in errors.iter().zip(expected) {
+            assert!(error.to_string().contains(expected), "{error}");
+        }
+    }
+
+    #[test]
+    fn collect_reports_missing_end_once() {
+        let (tree, errors) = collect("And(a, Or(b, c");
+        assert_eq!(tree, None);
+        assert_eq!(errors, vec![ParseError::UnbalancedParens {
+            opened: 2,
+            closed: 0,
+            span:   Span {
+                start: 9,
+                end:   10,
+            },
+        }]);
+    }
+
+    #[test]
+    fn collect_reports_trailing_tokens() {
+        let (tree, errors) = collect("Nand(a, b))");
+        assert_eq!(tree, None);
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn unexpected_token_has_span() {
+        let err = start(&mut lex("Nand(a, b) c").peekable()).unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::UnexpectedToken(Some(Spanned {
+                inner: Token::VarIdent("c".to_owned()),
+                span:  Span {
+                    start: 11,
+                    end:   12,
+                },
+            }))
+        );
+        assert_eq!(
+            err.span(),
+            Some(Span {
+                start: 11,
+                end:   12,
+            })
+        );
+    }
+
+    #[test]
+    fn missing_token_has_no_span() {
+        let err = start(&mut lex("let g = a in").peekable()).unwrap_err();
+        assert_eq!(err, ParseError::UnexpectedToken(None));
+        assert_eq!(err.span(), None);
+    }
+
+    #[test]
+    fn missing_closing_paren_is_unbalanced() {
+        let err = start(&mut lex("And(a, Not(b)").peekable()).unwrap_err();
+        assert_eq!(err, ParseError::UnbalancedParens {
+            opened: 2,
+            closed: 1,
+            span:   Span { start: 3, end: 4 },
+        });
+        assert_eq!(
+            err.to_string(),
+            "unbalanced parentheses: 2 opened, but 1 closed"
+        );
+        assert!(matches!(
+            parse("(a | b & c"),
+            Err(ParseError::UnbalancedParens {
+                opened: 1,
+                closed: 0,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn extra_closing_paren_is_unbalanced() {
+        let err = start(&mut lex("And(a, b))").peekable()).unwrap_err();
+        assert_eq!(err, ParseError::UnbalancedParens {
+            opened: 1,
+            closed: 2,
+            span:   Span {
+                start: 9,
+                end:   10,
+            },
+        });
+        assert_eq!(
+            err.span(),
+            Some(Span {
+                start: 9,
+                end:   10,
+            })
+        );
+        let err = start_multi(&mut lex("Not(a)\n)Not(b)").peekable());
+        assert!(matches!(
+            err,
+            Err(ParseError::UnbalancedParens {
+                opened: 1,
+                closed: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn start_multi_parses_each_expression() {
+        let trees =
+            start_multi(&mut lex("And(a, b)\nNot(c)\n").peekable()).unwrap();
+        assert_eq!(trees, vec![
+            start(&mut lex("And(a, b)").peekable()).unwrap(),
+            start(&mut lex("Not(c)").peekable()).unwrap(),
+        ]);
+    }
+
+    #[test]
+    fn start_multi_rejects_incomplete_expression() {
+        let err =
+            start_multi(&mut lex("And(a, b)\nNot(c").peekable()).unwrap_err();
+        assert_eq!(err, ParseError::UnbalancedParens {
+            opened: 2,
+            closed: 1,
+            span:   Span {
+                start: 13,
+                end:   14,
+            },
+        });
+    }
+
+    #[test]
+    fn start_multi_requires_an_expression() {
+        assert_eq!(
+            start_multi(&mut lex("").peekable()),
+            Err(ParseError::EmptyInput)
+        );
+    }
+
+    #[test]
+    fn empty_input_is_reported_as_such() {
+        for input in ["", " \t\n ", "# Nothing but a comment\n# And another"] {
+            assert_eq!(parse(input), Err(ParseError::EmptyInput), "{input:?}");
+            assert_eq!(collect(input), (None, vec![ParseError::EmptyInput]));
+        }
+        // Definitions without an expression aren't empty.
+        assert_eq!(
+            parse("def Id(a) = a"),
+            Err(ParseError::UnexpectedToken(None))
+        );
+    }
+
+    fn parse(input: &str) -> Result<Node> {
+        start(&mut lex(input).peekable())
+    }
+
+    #[test]
+    fn let_binding_is_substituted() {
+        assert_eq!(
+            parse("let g = Nand(a, b) in And(g, g)"),
+            parse("And(Nand(a, b), Nand(a, b))")
+        );
+    }
+
+    fn parse_strict(input: &str) -> Result<Node> {
+        let options = ParseOptions {
+            strict: true,
+            ..Default::default()
+        };
+        start_with(&mut lex(input).peekable(), options)
+    }
+
+    #[test]
+    fn unused_binding_is_dropped_by_default() {
+        assert_eq!(parse("let g = Nand(a, b) in Not(a)"), parse("Not(a)"));
+    }
+
+    #[test]
+    fn unused_binding_is_an_error_in_strict_mode() {
+        assert_eq!(
+            parse_strict("let g = Nand(a, b) in Not(a)"),
+            Err(ParseError::UnusedBinding("g".to_owned()))
+        );
+        // The outer `g` is shadowed before it's used.
+        assert_eq!(
+            parse_strict("let g = a in let g = b in g"),
+            Err(ParseError::UnusedBinding("g".to_owned()))
+        );
+        assert_eq!(
+            ParseError::UnusedBinding("g".to_owned()).to_string(),
+            "binding 'g' is never used"
+        );
+    }
+
+    #[test]
+    fn used_bindings_are_accepted_in_strict_mode() {
+        assert_eq!(
+            parse_strict("let g = Not(a) in let h = Or(g, b) in h"),
+            parse("Or(Not(a), b)")
+        );
+        // Parameters of definitions aren't `let` bindings.
+        assert_eq!(parse_strict("def Left(a, b) = a\nLeft(x, y)"), parse("x"));
+    }
+
+    #[test]
+    fn let_binding_can_refer_to_earlier_bindings() {
+        assert_eq!(
+            parse("let g = Not(a) in let h = Or(g, b) in And(h, g)"),
+            parse("And(Or(Not(a), b), Not(a))")
+        );
+    }
+
+    #[test]
+    fn let_binding_shadows_outer_binding() {
+        assert_eq!(
+            parse("let g = a in let g = Not(g) in And(g, b)"),
+            parse("And(Not(a), b)")
+        );
+    }
+
+    #[test]
+    fn unbound_variables_stay_variables() {
+        assert_eq!(parse("let g = a in And(g, h)"), parse("And(a, h)"));
+    }
+
+    #[test]
+    fn binding_used_before_its_definition_is_undefined() {
+        assert_eq!(
+            parse("let g = Not(h) in let h = a in And(g, h)"),
+            Err(ParseError::UndefinedBinding("h".to_owned()))
+        );
+    }
+
+    #[test]
+    fn binding_used_in_its_own_value_is_undefined() {
+        assert_eq!(
+            parse("let g = And(g, a) in Not(g)"),
+            Err(ParseError::UndefinedBinding("g".to_owned()))
+        );
+    }
+
+    #[test]
+    fn bindings_are_scoped_to_one_expression() {
+        let trees =
+            start_multi(&mut lex("let g = a in Not(g)\nNot(g)").peekable());
+        assert_eq!(
+            trees,
+            Ok(vec![parse("Not(a)").unwrap(), Node::Func {
+                id:   Id::Not,
+                args: vec![Node::Var { id: "g".to_owned() }],
+            }])
+        );
+    }
+
+    fn program(input: &str) -> Result<Program> {
+        start_program(&mut lex(input).peekable())
+    }
+
+    const MAJ: &str =
+        "def Maj(a, b, c) = Or(And(a, b), Or(And(a, c), And(b, c)))\n";
+
+    #[test]
+    fn program_keeps_definitions() {
+        let program = program(&format!("{MAJ}Maj(x, y, z)")).unwrap();
+        assert_eq!(program.defs.len(), 1);
+        assert_eq!(program.defs[0].name, "Maj");
+        assert_eq!(program.defs[0].params, ["a", "b", "c"]);
+        assert_eq!(
+            Ok(program.defs[0].body.clone()),
+            parse("Or(And(a, b), Or(And(a, c), And(b, c)))")
+        );
+    }
+
+    #[test]
+    fn call_of_definition_is_expanded() {
+        assert_eq!(
+            program(&format!("{MAJ}Maj(x, Not(y), 1)")).map(|p| p.main),
+            parse("Or(And(x, Not(y)), Or(And(x, 1), And(Not(y), 1)))")
+        );
+    }
+
+    #[test]
+    fn definition_overrides_builtin() {
+        assert_eq!(
+            parse("def Not(x) = Nor(x, x)\nAnd(Not(a), b)"),
+            parse("And(Nor(a, a), b)")
+        );
+    }
+
+    #[test]
+    fn definition_is_visible_in_later_expressions() {
+        let trees = start_multi(
+            &mut lex("def Id(x) = And(x, x)\nId(a)\nId(b)").peekable(),
+        );
+        assert_eq!(
+            trees,
+            Ok(vec![
+                parse("And(a, a)").unwrap(),
+                parse("And(b, b)").unwrap()
+            ])
+        );
+    }
+
+    #[test]
+    fn call_with_wrong_number_of_arguments_is_rejected() {
+        assert_eq!(
+            parse(&format!("{MAJ}Maj(x, y)")),
+            Err(ParseError::ArityMismatch {
+                name:     "Maj".to_owned(),
+                expected: 3,
+                found:    2,
+            })
+        );
+    }
+
+    #[test]
+    fn definition_body_must_only_use_parameters() {
+        assert_eq!(
+            parse("def Foo(a) = And(a, b)\nFoo(x)"),
+            Err(ParseError::UndefinedBinding("b".to_owned()))
+        );
+    }
+
+    fn nested_nots(depth: usize) -> String {
+        "Not(".repeat(depth) + "a" + &")".repeat(depth)
+    }
+
+    #[test]
+    fn nesting_up_to_max_depth_is_accepted() {
+        let options = ParseOptions {
+            max_depth: 3,
+            ..Default::default()
+        };
+        let input = nested_nots(3);
+        assert!(start_with(&mut lex(&input).peekable(), options).is_ok());
+        let input = "let x = a in Not(Not(x))";
+        assert!(start_with(&mut lex(input).peekable(), options).is_ok());
+    }
+
+    #[test]
+    fn nesting_beyond_max_depth_is_rejected() {
+        let options = ParseOptions {
+            max_depth: 3,
+            ..Default::default()
+        };
+        let input = nested_nots(4);
+        assert_eq!(
+            start_with(&mut lex(&input).peekable(), options),
+            Err(ParseError::MaxDepthExceeded)
+        );
+        let input = "let x = a in let y = x in Not(Not(y))";
+        assert_eq!(
+            start_with(&mut lex(input).peekable(), options),
+            Err(ParseError::MaxDepthExceeded)
+        );
+    }
+
+    #[test]
+    fn arguments_beyond_max_args_are_rejected() {
+        let options = ParseOptions {
+            max_args: 3,
+            ..Default::default()
+        };
+        let parse =
+            |input: &str| start_with(&mut lex(input).peekable(), options);
+        assert!(parse("And(a, b, c,)").is_ok());
+        assert!(parse("a | b | c").is_ok());
+        let too_many = ParseError::TooManyArgs { limit: 3 };
+        assert_eq!(parse("And(a, b, c, d)"), Err(too_many.clone()));
+        assert_eq!(parse("Not(a & b & c & d)"), Err(too_many.clone()));
+        assert_eq!(too_many.to_string(), "function has more than 3 arguments");
+    }
+
+    #[test]
+    fn default_max_args_stops_wide_input() {
+        // The parser stops right after the limit, no matter how
+        // much input follows.
+        let mut input = "And(a".to_owned();
+        input.push_str(&", a".repeat(1 << 20));
+        input.push(')');
+        assert_eq!(
+            parse(&input),
+            Err(ParseError::TooManyArgs { limit: 1 << 16 })
+        );
+    }
+
+    #[test]
+    fn default_max_depth_stops_deep_input() {
+        let input = nested_nots(100_000);
+        assert_eq!(parse(&input), Err(ParseError::MaxDepthExceeded));
+    }
+
+    #[test]
+    fn function_ids_are_case_insensitive() {
+        let and = parse("And(a, b)").unwrap();
+        for input in ["AND(a, b)", "ANd(a, b)", "AnD(a, b)"] {
+            assert_eq!(parse(input), Ok(and.clone()), "parsing {input}");
+        }
+        assert_eq!(Id::parse("NoR", 2), Some(Id::Nor));
+        assert_eq!(Id::parse("XNOR", 2), Some(Id::Xnor));
+        assert_eq!(Id::parse("IMP", 2), Some(Id::Imp));
+        // Without a capital first letter it's not a function.
+        assert!(parse("aND(a, b)").is_err());
+    }
+
+    #[test]
+    fn longer_function_ids_are_unknown() {
+        assert_eq!(
+            parse("Andx(a, b)"),
+            Err(ParseError::InvalidFunctionId("Andx".to_owned()))
+        );
+        assert_eq!(Id::parse("Nandu", 2), None);
+        assert_eq!(Id::parse("Or", 1), None);
+    }
+
+    #[test]
+    fn comments_can_be_inside_expressions() {
+        assert_eq!(parse("And(a, # this is b\n b)"), parse("And(a, b)"));
+        assert_eq!(
+            parse("# Half adder\nXor(a, # (carry, ignored)\n b) # sum"),
+            parse("Xor(a, b)")
+        );
+    }
+
+    #[test]
+    fn infix_and_is_and() {
+        assert_eq!(parse("a & b"), parse("And(a, b)"));
+        assert_eq!(parse("a & b & c"), parse("And(a, b, c)"));
+        assert_eq!(parse("(a & b) & c"), parse("And(And(a, b), c)"));
+    }
+
+    #[test]
+    fn infix_operators_have_precedence() {
+        assert_eq!(parse("a | b & c"), parse("a | (b & c)"));
+        assert_eq!(parse("a | b & c"), parse("Or(a, And(b, c))"));
+        assert_eq!(parse("a ^ b & c | d"), parse("Or(Xor(a, And(b, c)), d)"));
+        assert_eq!(parse("~a & ~~b"), parse("And(Not(a), Not(Not(b)))"));
+        assert_eq!(parse("~(a | b)"), parse("Not(Or(a, b))"));
+    }
+
+    #[test]
+    fn infix_xor_is_nested_to_the_left() {
+        assert_eq!(parse("a ^ b ^ c"), parse("Xor(Xor(a, b), c)"));
+    }
+
+    #[test]
+    fn infix_and_function_calls_can_be_mixed() {
+        assert_eq!(
+            parse("Nand(a | b, c) & ~Or(d, 1)"),
+            parse("And(Nand(Or(a, b), c), Not(Or(d, 1)))")
+        );
+        assert_eq!(
+            parse("let g = a & b in g | g"),
+            parse("Or(And(a, b), And(a, b))")
+        );
+    }
+
+    #[test]
+    fn infix_rejects_missing_operand() {
+        assert!(parse("a &").is_err());
+        assert!(parse("(a | b").is_err());
+        assert!(parse("a | & b").is_err());
+    }
+
+    #[test]
+    fn unknown_id_suggests_closest_known_id() {
+        assert_eq!(Id::suggest("Ans"), Some(AND_ID));
+        assert_eq!(Id::suggest("NANF"), Some(NAND_ID));
+        assert_eq!(Id::suggest("Nnad"), Some(NAND_ID));
+        assert_eq!(
+            parse("Ans(a, b)").unwrap_err().to_string(),
+            "unknown function id 'Ans', did you mean 'And'?"
+        );
+    }
+
+    #[test]
+    fn unknown_id_without_similar_known_id() {
+        assert_eq!(Id::suggest("Multiplexer"), None);
+        assert_eq!(Id::suggest("Xy"), None);
+        assert_eq!(
+            parse("Multiplexer(a, b)").unwrap_err().to_string(),
+            "unknown function id 'Multiplexer'"
+        );
+        // Wrong number of arguments for a known id.
+        assert_eq!(
+            parse("Not(a, b)").unwrap_err().to_string(),
+            "unknown function id 'Not'"
+        );
+    }
+
+    #[test]
+    fn known_ids_parse_to_their_id() {
+        for &(name, id, arity) in Id::all() {
+            assert_eq!(Id::parse(name, arity), Some(id));
+            assert_eq!(Id::parse(&name.to_uppercase(), arity), Some(id));
+            assert_eq!(id.to_string(), name);
+            assert_eq!(id.arity(), arity);
+            assert_eq!(Id::parse(name, arity + 1).is_some(), id.is_variadic());
+            assert_eq!(Id::parse(name, arity - 1), None);
+        }
+    }
+
+    #[test]
+    fn logical_symbols_parse_like_their_functions() {
+        assert_eq!(parse("¬a"), parse("Not(a)"));
+        assert_eq!(parse("a ∧ b ∧ c"), parse("And(a, b, c)"));
+        assert_eq!(parse("¬a ∨ b"), parse("Or(Not(a), b)"));
+        assert_eq!(parse("a ⊼ b"), parse("Nand(a, b)"));
+        // `Nand` isn't associative, so it's nested to the left
+        // instead of becoming a single call.
+        assert_eq!(parse("a ⊼ b ⊼ c"), parse("Nand(Nand(a, b), c)"));
+        assert_eq!(parse("a ⊼ b & c"), parse("And(Nand(a, b), c)"));
+        assert_eq!(parse("a | b ⊼ c"), parse("Or(a, Nand(b, c))"));
+    }
+
+    #[test]
+    fn unknown_symbol_is_reported_with_its_text() {
+        let err = parse("a + b").unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::UnexpectedToken(Some(Spanned {
+                inner: Token::LexError("+".to_owned()),
+                span:  Span { start: 2, end: 3 },
+            }))
+        );
+        assert_eq!(err.to_string(), "unexpected token '+'");
+        assert_eq!(
+            parse("a && b").unwrap_err().to_string(),
+            "unexpected token '&'"
+        );
+    }
+
+    #[test]
+    fn trailing_delimiter_is_accepted() {
+        assert_eq!(parse("And(a, b,)"), parse("And(a, b)"));
+        assert_eq!(parse("Not(Or(a, b,),)"), parse("Not(Or(a, b))"));
+    }
+
+    #[test]
+    fn double_delimiter_is_rejected() {
+        assert_eq!(
+            parse("And(a,, b)"),
+            Err(ParseError::UnexpectedToken(Some(Spanned {
+                inner: Token::Delim,
+                span:  Span { start: 6, end: 7 },
+            })))
+        );
+        assert_eq!(
+            parse("And(,)"),
+            Err(ParseError::UnexpectedToken(Some(Spanned {
+                inner: Token::Delim,
+                span:  Span { start: 4, end: 5 },
+            })))
+        );
+        assert!(parse("And(a, b,,)").is_err());
+    }
 
     #[test]
     fn parse_accepts_and() {
@@ -156,6 +1496,7 @@ mod tests {
             Token::RParen,
         ]
         .into_iter()
+        .map(unspanned)
         .peekable();
         func(&mut token_stream).unwrap();
     }
@@ -171,6 +1512,7 @@ mod tests {
             Token::RParen,
         ]
         .into_iter()
+        .map(unspanned)
         .peekable();
         func(&mut token_stream).unwrap();
     }
@@ -186,10 +1528,104 @@ mod tests {
             Token::RParen,
         ]
         .into_iter()
+        .map(unspanned)
+        .peekable();
+        func(&mut token_stream).unwrap();
+    }
+
+    #[test]
+    fn parse_accepts_variadic_and() {
+        let mut token_stream = [
+            Token::FuncIdent("And".to_owned()),
+            Token::LParen,
+            Token::VarIdent("a".to_owned()),
+            Token::Delim,
+            Token::VarIdent("b".to_owned()),
+            Token::Delim,
+            Token::VarIdent("c".to_owned()),
+            Token::RParen,
+        ]
+        .into_iter()
+        .map(unspanned)
         .peekable();
         func(&mut token_stream).unwrap();
     }
 
+    #[test]
+    fn parse_rejects_variadic_nand() {
+        let mut token_stream = [
+            Token::FuncIdent("Nand".to_owned()),
+            Token::LParen,
+            Token::VarIdent("a".to_owned()),
+            Token::Delim,
+            Token::VarIdent("b".to_owned()),
+            Token::Delim,
+            Token::VarIdent("c".to_owned()),
+            Token::RParen,
+        ]
+        .into_iter()
+        .map(unspanned)
+        .peekable();
+        assert_eq!(
+            func(&mut token_stream),
+            Err(ParseError::InvalidFunctionId("Nand".to_owned()))
+        );
+    }
+
+    #[test]
+    fn parse_accepts_constants() {
+        let mut token_stream = [
+            Token::FuncIdent("Nand".to_owned()),
+            Token::LParen,
+            Token::Const(true),
+            Token::Delim,
+            Token::Const(false),
+            Token::RParen,
+        ]
+        .into_iter()
+        .map(unspanned)
+        .peekable();
+        assert_eq!(func(&mut token_stream).unwrap(), Node::Func {
+            id:   Id::Nand,
+            args: vec![Node::Const { value: true }, Node::Const {
+                value: false,
+            }],
+        });
+    }
+
+    #[test]
+    fn parse_accepts_not() {
+        let mut token_stream = [
+            Token::FuncIdent("Not".to_owned()),
+            Token::LParen,
+            Token::VarIdent("a".to_owned()),
+            Token::RParen,
+        ]
+        .into_iter()
+        .map(unspanned)
+        .peekable();
+        func(&mut token_stream).unwrap();
+    }
+
+    #[test]
+    fn parse_rejects_not_with_two_args() {
+        let mut token_stream = [
+            Token::FuncIdent("Not".to_owned()),
+            Token::LParen,
+            Token::VarIdent("a".to_owned()),
+            Token::Delim,
+            Token::VarIdent("b".to_owned()),
+            Token::RParen,
+        ]
+        .into_iter()
+        .map(unspanned)
+        .peekable();
+        assert_eq!(
+            func(&mut token_stream),
+            Err(ParseError::InvalidFunctionId("Not".to_owned()))
+        );
+    }
+
     #[test]
     fn parse_accepts_nested_functions() {
         let mut token_stream = [
@@ -206,6 +1642,7 @@ mod tests {
             Token::RParen,
         ]
         .into_iter()
+        .map(unspanned)
         .peekable();
         func(&mut token_stream).unwrap();
     }
@@ -0,0 +1,74 @@
+use crate::tree::Node;
+
+// A user-defined function. The body only refers to the
+// parameters, which are replaced by the arguments of a call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Def {
+    pub name:   String,
+    pub params: Vec<String>,
+    pub body:   Node,
+}
+
+impl Def {
+    // Expand a call to this function. Each parameter in the
+    // body is renamed to the argument at the same position,
+    // all at once, so that arguments which contain variables
+    // named like the parameters are never replaced again.
+    pub fn expand(&self, args: &[Node]) -> Node {
+        debug_assert_eq!(self.params.len(), args.len());
+        let mut tree = self.body.clone();
+        self.substitute(&mut tree, args);
+        tree
+    }
+
+    fn substitute(&self, tree: &mut Node, args: &[Node]) {
+        match tree {
+            Node::Func { args: inner, .. } => {
+                for arg in inner.iter_mut() {
+                    self.substitute(arg, args);
+                }
+            },
+            Node::Var { id } => {
+                if let Some(i) = self.params.iter().position(|p| p == id) {
+                    *tree = args[i].clone();
+                }
+            },
+            Node::Const { .. } => {},
+        }
+    }
+}
+
+// A complete input: function definitions followed by the
+// expression to translate. Calls to the definitions are
+// already expanded in `main`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub defs: Vec<Def>,
+    pub main: Node,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Id;
+
+    fn var(id: &str) -> Node {
+        Node::Var { id: id.to_owned() }
+    }
+
+    #[test]
+    fn expand_replaces_all_parameters_at_once() {
+        let def = Def {
+            name:   "Swap".to_owned(),
+            params: vec!["a".to_owned(), "b".to_owned()],
+            body:   Node::Func {
+                id:   Id::Nand,
+                args: vec![var("b"), var("a")],
+            },
+        };
+        assert_eq!(def.expand(&[var("b"), var("a")]), Node::Func {
+            id:   Id::Nand,
+            args: vec![var("a"), var("b")],
+        });
+    }
+}
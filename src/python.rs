@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use crate::names::{is_simple, output_names};
+use crate::parse::Id;
+use crate::tree::Node;
+
+// Python keywords which could be variable names here. Variables
+// with these names are renamed like those which aren't Python
+// identifiers.
+const KEYWORDS: [&str; 35] = [
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break",
+    "class", "continue", "def", "del", "elif", "else", "except", "finally",
+    "for", "from", "global", "if", "import", "in", "is", "lambda", "nonlocal",
+    "not", "or", "pass", "raise", "return", "try", "while", "with", "yield",
+];
+
+impl Node {
+    // Emit the expression with Python's boolean operators, so that
+    // it can be evaluated in Python once the variables are defined.
+    // Every argument which isn't a variable or a constant is put in
+    // parentheses. Variables whose names aren't Python identifiers
+    // are renamed.
+    pub fn to_python(&self) -> String {
+        let vars: Vec<&str> = self.variables().into_iter().collect();
+        let is_valid = |id: &str| is_simple(id) && !KEYWORDS.contains(&id);
+        python(self, &output_names(&vars, is_valid))
+    }
+}
+
+// `node` in Python, with the variables called like in `names`.
+fn python(node: &Node, names: &HashMap<&str, String>) -> String {
+    match node {
+        Node::Func { id, args } => {
+            let args: Vec<String> =
+                args.iter().map(|arg| python_arg(arg, names)).collect();
+            match id {
+                Id::And => args.join(" and "),
+                Id::Or => args.join(" or "),
+                Id::Nand => format!("not ({})", args.join(" and ")),
+                Id::Not => format!("not {}", args[0]),
+                Id::Xor => args.join(" != "),
+                Id::Nor => format!("not ({})", args.join(" or ")),
+                Id::Xnor => args.join(" == "),
+                Id::Imp => format!("(not {}) or {}", args[0], args[1]),
+            }
+        },
+        Node::Var { id } => names[id.as_str()].clone(),
+        Node::Const { value: true } => "True".to_owned(),
+        Node::Const { value: false } => "False".to_owned(),
+    }
+}
+
+fn python_arg(arg: &Node, names: &HashMap<&str, String>) -> String {
+    match arg {
+        Node::Func { .. } => format!("({})", python(arg, names)),
+        Node::Var { .. } | Node::Const { .. } => python(arg, names),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lex::lex;
+    use crate::parse::start;
+    use crate::tree::Node;
+
+    fn parse(input: &str) -> Node {
+        start(&mut lex(input).peekable()).unwrap()
+    }
+
+    #[test]
+    fn python_of_nand() {
+        assert_eq!(parse("Nand(a, b)").to_python(), "not (a and b)");
+    }
+
+    #[test]
+    fn python_parenthesizes_nested_functions() {
+        assert_eq!(
+            parse("Nand(Nand(a, b), 1)").to_python(),
+            "not ((not (a and b)) and True)"
+        );
+        assert_eq!(
+            parse("Or(And(a, b, c), Not(Xor(a, 0)), Imp(a, Xnor(b, c)))")
+                .to_python(),
+            "(a and b and c) or (not (a != False)) or ((not a) or (b == c))"
+        );
+    }
+
+    #[test]
+    fn python_renames_keywords() {
+        assert_eq!(parse("Nor(lambda, x)").to_python(), "not (lambda_ or x)");
+        assert_eq!(parse("Xor(if, if_)").to_python(), "if__ != if_");
+    }
+
+    #[test]
+    fn python_renames_quoted_variables() {
+        assert_eq!(
+            parse("And(`my var`, `a\"b`, my_var)").to_python(),
+            "my_var_ and a_b and my_var"
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use crate::parse::Id;
+use crate::tree::Node;
+
+// SplitMix64, which is small, fast and good enough to build
+// test inputs. The same seed always gives the same numbers.
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // A number in `0..n`.
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+// Most functions which a tree from `random_expr` may have.
+// Functions have more than one argument which is a function on
+// average, so without a limit, deep trees grow without bound.
+const MAX_FUNCS: usize = 1024;
+
+// Build a random tree out of the built-in functions, which
+// uses the variables in `vars` and the constants as leaves.
+// No path from the root to a leaf has more than `max_depth`
+// functions on it, and there are at most 1024 functions. The
+// same arguments always give the same tree. The names in `vars`
+// should be valid variable names, so that the tree can be
+// parsed again after printing it.
+pub fn random_expr(seed: u64, max_depth: usize, vars: &[&str]) -> Node {
+    // `depth` is the number of functions which may still be
+    // nested below this point, and `funcs` the number of
+    // functions which may still be built.
+    fn node(
+        rng: &mut Rng,
+        depth: usize,
+        is_root: bool,
+        vars: &[&str],
+        funcs: &mut usize,
+    ) -> Node {
+        // The root is always a function, if it may be one at all.
+        let is_leaf =
+            depth == 0 || (!is_root && rng.below(3) == 0) || *funcs == 0;
+        if is_leaf {
+            // Mostly variables, but constants now and then.
+            return match rng.below(8) {
+                0 => Node::Const {
+                    value: rng.below(2) == 1,
+                },
+                _ if vars.is_empty() => Node::Const {
+                    value: rng.below(2) == 1,
+                },
+                _ => Node::Var {
+                    id: vars[rng.below(vars.len())].to_owned(),
+                },
+            };
+        }
+
+        *funcs -= 1;
+        let ids = Id::all();
+        let (_, id, arity) = ids[rng.below(ids.len())];
+        let num_args = if id.is_variadic() {
+            arity + rng.below(2)
+        } else {
+            arity
+        };
+        debug_assert!(id.accepts(num_args));
+        let args = (0..num_args)
+            .map(|_| node(rng, depth - 1, false, vars, funcs))
+            .collect();
+        Node::Func { id, args }
+    }
+
+    node(&mut Rng(seed), max_depth, true, vars, &mut { MAX_FUNCS })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VARS: [&str; 3] = ["a", "b", "x1"];
+
+    #[test]
+    fn same_seed_gives_same_expression() {
+        assert_eq!(random_expr(7, 5, &VARS), random_expr(7, 5, &VARS));
+        assert_ne!(random_expr(7, 5, &VARS), random_expr(8, 5, &VARS));
+    }
+
+    #[test]
+    fn random_expressions_parse_again() {
+        for seed in 0..200 {
+            let tree = random_expr(seed, 4, &VARS);
+            assert_eq!(tree.to_string().parse::<Node>(), Ok(tree));
+        }
+    }
+
+    #[test]
+    fn random_expressions_are_bounded_by_depth() {
+        for seed in 0..200 {
+            let tree = random_expr(seed, 3, &VARS);
+            assert!(tree.depth() <= 3);
+            assert!(matches!(tree, Node::Func { .. }));
+        }
+        assert!(!matches!(random_expr(0, 0, &VARS), Node::Func { .. }));
+        assert!(matches!(random_expr(0, 0, &[]), Node::Const { .. }));
+    }
+
+    #[test]
+    fn deep_random_expressions_stay_small() {
+        for seed in 4..7 {
+            let tree = random_expr(seed, 1000, &VARS);
+            assert!(tree.stats().gate_count <= MAX_FUNCS);
+        }
+    }
+}
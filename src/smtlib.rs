@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::names::output_names;
+use crate::parse::Id;
+use crate::tree::Node;
+
+impl Node {
+    // Emit an SMT-LIB script which declares every variable as a
+    // `Bool` constant and defines the expression as the function
+    // `circuit`, with `_` appended if a variable has that name.
+    // Functions are kept as they are; use `to_nand` first to get
+    // the `Nand` form.
+    pub fn to_smtlib(&self) -> String {
+        let vars: Vec<&str> = self.variables().into_iter().collect();
+        let names = smtlib_names(&vars);
+        let mut out = String::new();
+        for var in vars {
+            writeln!(out, "(declare-const {} Bool)", names[var]).unwrap();
+        }
+        // `|circuit|` is the same symbol as `circuit`.
+        let mut circuit = String::from("|circuit|");
+        while names.values().any(|name| *name == circuit) {
+            circuit.insert(circuit.len() - 1, '_');
+        }
+        write!(out, "(define-fun {circuit} () Bool {})", self.term(&names))
+            .unwrap();
+        out
+    }
+
+    // The expression as an SMT-LIB term, without declaring the
+    // variables it uses.
+    pub fn to_smtlib_term(&self) -> String {
+        let vars: Vec<&str> = self.variables().into_iter().collect();
+        self.term(&smtlib_names(&vars))
+    }
+
+    fn term(&self, names: &HashMap<&str, String>) -> String {
+        match self {
+            Node::Func { id, args } => {
+                let args: Vec<String> =
+                    args.iter().map(|arg| arg.term(names)).collect();
+                let args = args.join(" ");
+                match id {
+                    Id::And => format!("(and {args})"),
+                    Id::Or => format!("(or {args})"),
+                    Id::Nand => format!("(not (and {args}))"),
+                    Id::Not => format!("(not {args})"),
+                    Id::Xor => format!("(xor {args})"),
+                    Id::Nor => format!("(not (or {args}))"),
+                    Id::Xnor => format!("(= {args})"),
+                    Id::Imp => format!("(=> {args})"),
+                }
+            },
+            Node::Var { id } => names[id.as_str()].clone(),
+            Node::Const { value } => value.to_string(),
+        }
+    }
+}
+
+// Symbols for the variables in `vars`. Every name is quoted with
+// `|`, so that none of them can be mistaken for a reserved word
+// or a function of the core theory. Names with `|` or `\` in them
+// can't be quoted, so these are renamed.
+fn smtlib_names<'a>(vars: &[&'a str]) -> HashMap<&'a str, String> {
+    output_names(vars, |id| !id.contains(['|', '\\']))
+        .into_iter()
+        .map(|(var, name)| (var, format!("|{name}|")))
+        .collect()
+}
+
+// An SMT-LIB script which is unsatisfiable exactly if `a` and `b`
+// compute the same function. Variables with the same name are
+// the same in both expressions.
+pub(crate) fn equivalence_query(a: &Node, b: &Node) -> String {
+    let vars: Vec<&str> =
+        a.variables().union(&b.variables()).copied().collect();
+    let names = smtlib_names(&vars);
+    let mut out = String::new();
+    for var in vars {
+        writeln!(out, "(declare-const {} Bool)", names[var]).unwrap();
+    }
+    writeln!(
+        out,
+        "(assert (not (= {} {})))",
+        a.term(&names),
+        b.term(&names)
+    )
+    .unwrap();
+    out.push_str("(check-sat)");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::lex;
+    use crate::parse::start;
+
+    fn parse(input: &str) -> Node {
+        start(&mut lex(input).peekable()).unwrap()
+    }
+
+    #[test]
+    fn smtlib_of_and() {
+        assert_eq!(
+            parse("And(a, b)").to_smtlib(),
+            "(declare-const |a| Bool)\n\
+             (declare-const |b| Bool)\n\
+             (define-fun |circuit| () Bool (and |a| |b|))"
+        );
+    }
+
+    #[test]
+    fn smtlib_of_lowered_and() {
+        let mut tree = parse("And(a, b)");
+        tree.to_nand();
+        assert_eq!(
+            tree.to_smtlib_term(),
+            "(not (and (not (and |a| |b|)) (not (and |a| |b|))))"
+        );
+    }
+
+    #[test]
+    fn smtlib_terms_of_all_functions() {
+        assert_eq!(
+            parse("Or(Xor(a, 1), Nor(b, 0), Xnor(a, b), Imp(a, Not(b)))")
+                .to_smtlib_term(),
+            "(or (xor |a| true) (not (or |b| false)) (= |a| |b|) (=> |a| \
+             (not |b|)))"
+        );
+    }
+
+    #[test]
+    fn smtlib_quotes_reserved_symbols() {
+        assert_eq!(
+            parse("Nand(true, and)").to_smtlib(),
+            "(declare-const |and| Bool)\n\
+             (declare-const |true| Bool)\n\
+             (define-fun |circuit| () Bool (not (and |true| |and|)))"
+        );
+        assert_eq!(
+            parse("Or(`=`, `=>`, `_`, `!`)").to_smtlib_term(),
+            "(or |=| |=>| |_| |!|)"
+        );
+    }
+
+    #[test]
+    fn smtlib_renames_circuit_if_a_variable_has_its_name() {
+        assert_eq!(
+            parse("Or(circuit, circuit_)").to_smtlib(),
+            "(declare-const |circuit| Bool)\n\
+             (declare-const |circuit_| Bool)\n\
+             (define-fun |circuit__| () Bool (or |circuit| |circuit_|))"
+        );
+    }
+
+    #[test]
+    fn smtlib_quotes_quoted_variables() {
+        assert_eq!(
+            parse("Or(`my var`, `a|b`)").to_smtlib(),
+            "(declare-const |a_b| Bool)\n\
+             (declare-const |my var| Bool)\n\
+             (define-fun |circuit| () Bool (or |my var| |a_b|))"
+        );
+    }
+
+    #[test]
+    fn equivalence_query_negates_equality() {
+        assert_eq!(
+            equivalence_query(&parse("Not(a)"), &parse("Nand(a, b)")),
+            "(declare-const |a| Bool)\n\
+             (declare-const |b| Bool)\n\
+             (assert (not (= (not |a|) (not (and |a| |b|)))))\n\
+             (check-sat)"
+        );
+    }
+}
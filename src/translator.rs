@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::tree::Node;
+
+// Lowers trees to `Nand`s and remembers every function it has
+// lowered, so that sub-trees which appear again, in the same
+// tree or in a later one, aren't lowered a second time. Sub-trees
+// are looked up by their canonical form, so `And(a, b)` and
+// `And(b, a)` share their translation.
+#[derive(Debug, Default)]
+pub struct Translator {
+    cache: HashMap<Node, Rc<Node>>,
+    hits:  usize,
+}
+
+impl Translator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Lower `node` like `to_nand` lowers its canonical form.
+    // Translating the same sub-tree again returns the same `Rc`.
+    pub fn translate_node(&mut self, node: &Node) -> Rc<Node> {
+        let mut canonical = node.clone();
+        canonical.canonicalize();
+        self.lower(&canonical)
+    }
+
+    // Number of functions whose translation was found in the
+    // cache instead of lowering them again.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    fn lower(&mut self, node: &Node) -> Rc<Node> {
+        let Node::Func { id, args } = node else {
+            return Rc::new(node.clone());
+        };
+        if let Some(lowered) = self.cache.get(node) {
+            self.hits += 1;
+            return Rc::clone(lowered);
+        }
+        let args = args.iter().map(|arg| (*self.lower(arg)).clone()).collect();
+        let lowered = Rc::new(Node::lower_wide_func(*id, args));
+        self.cache.insert(node.clone(), Rc::clone(&lowered));
+        lowered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Node {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn same_subexpression_is_translated_once() {
+        let mut translator = Translator::new();
+        let first = translator.translate_node(&parse("And(x, y)"));
+        assert_eq!(translator.hits(), 0);
+        let second = translator.translate_node(&parse("And(y, x)"));
+        assert!(Rc::ptr_eq(&first, &second));
+        assert_eq!(translator.hits(), 1);
+
+        // The `And` in here is found in the cache, too.
+        translator.translate_node(&parse("Or(Not(z), And(x, y))"));
+        assert_eq!(translator.hits(), 2);
+    }
+
+    #[test]
+    fn translations_match_to_nand() {
+        let mut translator = Translator::new();
+        for input in [
+            "Or(And(a, b, c), Not(Xor(a, 0)), Imp(a, Xnor(b, c)))",
+            "Nor(Imp(b, a), And(a, b, c))",
+            "a",
+        ] {
+            let mut lowered = parse(input);
+            lowered.canonicalize();
+            lowered.to_nand();
+            assert_eq!(*translator.translate_node(&parse(input)), lowered);
+        }
+    }
+}
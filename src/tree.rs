@@ -1,83 +1,1173 @@
-use crate::parse::Id;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use crate::lex::{lex, needs_quotes, quote};
+use crate::parse::{start, Id, ParseError};
 
 // Single node in a tree.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Node {
     Func { id: Id, args: Vec<Node> },
     Var { id: String },
+    Const { value: bool },
 }
 
 // implement all the tree transformations for Node
 impl Node {
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_nand(&mut self) {
+        self.to_nand_counted();
+    }
+
+    // Same as `to_nand`, but returns the number of functions which
+    // were rewritten into `Nand`s. An `And` or `Or` with `n`
+    // arguments counts as `n - 1` binary functions.
+    pub fn to_nand_counted(&mut self) -> usize {
+        self.fold_variadic();
+        let mut rewrites = 0;
+        self.rewrite_post_order(|id, args| {
+            if id != Id::Nand {
+                rewrites += 1;
+            }
+            Node::lower_func(id, args)
+        });
+        rewrites
+    }
+
+    // Same as `to_nand`, but `And`s and `Or`s with more than two
+    // arguments become balanced trees of binary functions, instead
+    // of folding them to the left. That computes the same, but
+    // the longest path through the circuit is shorter.
+    pub fn to_nand_balanced(&mut self) {
+        self.balance_variadic();
+        self.to_nand();
+    }
+
+    // Rewrite `And` and `Or` with more than two arguments into a
+    // balanced tree of binary functions. For example,
+    // `And(a, b, c, d)` becomes `And(And(a, b), And(c, d))`.
+    pub fn balance_variadic(&mut self) {
+        fn balance(id: Id, mut args: Vec<Node>) -> Node {
+            if args.len() == 1 {
+                return args.pop().unwrap();
+            }
+            if args.len() == 2 {
+                return Node::Func { id, args };
+            }
+            let right = args.split_off(args.len() / 2);
+            Node::Func {
+                id,
+                args: vec![balance(id, args), balance(id, right)],
+            }
+        }
+
+        self.rewrite_post_order(|id, args| {
+            if id.is_variadic() && args.len() > 2 {
+                balance(id, args)
+            } else {
+                Node::Func { id, args }
+            }
+        });
+    }
+
+    // Do a single step of `to_nand` and return whether there was
+    // anything left to do. The step rewrites the first function in
+    // post-order which isn't a `Nand` yet, so its arguments are
+    // already lowered. `And` and `Or` with more than two arguments
+    // are first folded into nested binary functions. Repeating
+    // this until it returns `false` gives the same tree as
+    // `to_nand`.
+    pub fn lower_step(&mut self) -> bool {
+        let Node::Func { id, args } = self else {
+            return false;
+        };
+        if args.iter_mut().any(Node::lower_step) {
+            return true;
+        }
+        if *id == Id::Nand {
+            return false;
+        }
+        let args = std::mem::take(args);
+        *self = if id.is_variadic() && args.len() > 2 {
+            Node::fold_variadic_func(*id, args)
+        } else {
+            Node::lower_func(*id, args)
+        };
+        true
+    }
+
+    // Replace every function in the tree by the result of `f`,
+    // innermost functions first. So when `f` is called, all
+    // arguments have been rewritten already. The traversal uses
+    // an explicit stack instead of recursion, so that deeply
+    // nested trees don't overflow the call stack.
+    pub(crate) fn rewrite_post_order(
+        &mut self,
+        mut f: impl FnMut(Id, Vec<Node>) -> Node,
+    ) {
+        // A function whose arguments are being rewritten. `done`
+        // are the ones which are finished, `todo` the rest.
+        struct Frame {
+            id:   Id,
+            done: Vec<Node>,
+            todo: std::vec::IntoIter<Node>,
+        }
+
+        let mut stack: Vec<Frame> = vec![];
+        let mut next = std::mem::replace(self, Node::Const { value: false });
+        loop {
+            // Descend into `next` until a leaf is reached.
+            let mut finished = match next {
+                Node::Func { id, args } => {
+                    stack.push(Frame {
+                        id,
+                        done: Vec::with_capacity(args.len()),
+                        todo: args.into_iter(),
+                    });
+                    None
+                },
+                leaf => Some(leaf),
+            };
+            // Go back up as long as all arguments are finished.
+            loop {
+                let Some(frame) = stack.last_mut() else {
+                    *self = finished.expect("root of the tree is finished");
+                    return;
+                };
+                frame.done.extend(finished.take());
+                match frame.todo.next() {
+                    Some(arg) => {
+                        next = arg;
+                        break;
+                    },
+                    None => {
+                        let frame = stack.pop().unwrap();
+                        finished = Some(f(frame.id, frame.done));
+                    },
+                }
+            }
+        }
+    }
+
+    // Variables which stand in for the arguments of a function
+    // while it's lowered on its own. Their names can't be parsed,
+    // so they can't clash with real variables.
+    pub(crate) fn placeholders(num_args: usize) -> Vec<Node> {
+        (0..num_args)
+            .map(|i| Node::Var {
+                id: format!("${i}"),
+            })
+            .collect()
+    }
+
+    // The argument which a variable from `placeholders` stands for.
+    pub(crate) fn placeholder_index(id: &str) -> Option<usize> {
+        id.strip_prefix('$')?.parse().ok()
+    }
+
+    // Rewrite `And` and `Or` with more than two arguments into
+    // nested binary functions, folding to the left. For example,
+    // `And(a, b, c)` becomes `And(And(a, b), c)`.
+    fn fold_variadic(&mut self) {
+        self.rewrite_post_order(Node::fold_variadic_func);
+    }
+
+    fn fold_variadic_func(id: Id, args: Vec<Node>) -> Node {
+        if matches!(id, Id::And | Id::Or) && args.len() > 2 {
+            let mut args = args.into_iter();
+            let first = args.next().unwrap();
+            args.fold(first, |acc, arg| Node::Func {
+                id,
+                args: vec![acc, arg],
+            })
+        } else {
+            Node::Func { id, args }
+        }
+    }
+
+    // Same as `lower_func`, but `And`s and `Or`s may have more
+    // than two arguments. They are folded to the left, like
+    // `to_nand` does.
+    pub(crate) fn lower_wide_func(id: Id, args: Vec<Node>) -> Node {
+        if id.is_variadic() && args.len() > 2 {
+            let mut args = args.into_iter();
+            let first = args.next().unwrap();
+            args.fold(first, |acc, arg| Node::lower_func(id, vec![acc, arg]))
+        } else {
+            Node::lower_func(id, args)
+        }
+    }
+
+    // Lower a single function whose arguments are lowered already.
+    pub(crate) fn lower_func(id: Id, args: Vec<Node>) -> Node {
+        match id {
+            Id::And => {
+                debug_assert_eq!(args.len(), 2);
+                let nested_1 = Node::Func {
+                    id:   Id::Nand,
+                    args: args.clone(),
+                };
+                let nested_2 = Node::Func { id: Id::Nand, args };
+                Node::Func {
+                    id:   Id::Nand,
+                    args: vec![nested_1, nested_2],
+                }
+            },
+            Id::Or => {
+                debug_assert_eq!(args.len(), 2);
+                let nested_1 = Node::Func {
+                    id:   Id::Nand,
+                    args: vec![args[0].clone(), args[0].clone()],
+                };
+                let nested_2 = Node::Func {
+                    id:   Id::Nand,
+                    args: vec![args[1].clone(), args[1].clone()],
+                };
+                Node::Func {
+                    id:   Id::Nand,
+                    args: vec![nested_1, nested_2],
+                }
+            },
+            Id::Not => {
+                debug_assert_eq!(args.len(), 1);
+                Node::Func {
+                    id:   Id::Nand,
+                    args: vec![args[0].clone(), args[0].clone()],
+                }
+            },
+            Id::Xor => {
+                debug_assert_eq!(args.len(), 2);
+                // The arguments are already lowered, so the
+                // shared `Nand(a, b)` is built from their
+                // final form.
+                let shared = Node::Func {
+                    id:   Id::Nand,
+                    args: args.clone(),
+                };
+                let nested_1 = Node::Func {
+                    id:   Id::Nand,
+                    args: vec![args[0].clone(), shared.clone()],
+                };
+                let nested_2 = Node::Func {
+                    id:   Id::Nand,
+                    args: vec![args[1].clone(), shared],
+                };
+                Node::Func {
+                    id:   Id::Nand,
+                    args: vec![nested_1, nested_2],
+                }
+            },
+            Id::Nor => {
+                debug_assert_eq!(args.len(), 2);
+                let nested_1 = Node::Func {
+                    id:   Id::Nand,
+                    args: vec![args[0].clone(), args[0].clone()],
+                };
+                let nested_2 = Node::Func {
+                    id:   Id::Nand,
+                    args: vec![args[1].clone(), args[1].clone()],
+                };
+                // `Nand(a', b')` is `Or(a, b)`, which is then
+                // inverted by using it as both arguments.
+                let or = Node::Func {
+                    id:   Id::Nand,
+                    args: vec![nested_1, nested_2],
+                };
+                Node::Func {
+                    id:   Id::Nand,
+                    args: vec![or.clone(), or],
+                }
+            },
+            Id::Xnor => {
+                debug_assert_eq!(args.len(), 2);
+                // Invert the lowered `Xor` by using it as both
+                // arguments.
+                let xor = Node::lower_func(Id::Xor, args);
+                Node::Func {
+                    id:   Id::Nand,
+                    args: vec![xor.clone(), xor],
+                }
+            },
+            Id::Imp => {
+                debug_assert_eq!(args.len(), 2);
+                // `Nand(a, b')` is `Or(a', b)`.
+                let not_b = Node::Func {
+                    id:   Id::Nand,
+                    args: vec![args[1].clone(), args[1].clone()],
+                };
+                Node::Func {
+                    id:   Id::Nand,
+                    args: vec![args[0].clone(), not_b],
+                }
+            },
+            Id::Nand => Node::Func { id, args },
+        }
+    }
+
+    // Propagate constants through all functions, bottom up. A
+    // function with only constant arguments becomes a constant
+    // itself, a constant which decides the result replaces the
+    // whole function and arguments which don't affect the result
+    // are dropped. For example, `And(a, 1)` becomes `a` and
+    // `And(0, And(b, c))` becomes `0`. Since every function is
+    // folded after its arguments, one pass reaches the fixpoint.
+    // This is meant to run before `to_nand`.
+    pub fn fold(&mut self) {
+        self.rewrite_post_order(Node::fold_func);
+    }
+
+    // Fold a single function whose arguments are folded already.
+    fn fold_func(id: Id, mut args: Vec<Node>) -> Node {
+        if args.iter().all(|arg| matches!(arg, Node::Const { .. })) {
+            let value = Node::Func { id, args }
+                .eval(&HashMap::new())
+                .expect("constants don't need any variables");
+            return Node::Const { value };
+        }
+
+        let is = |arg: &Node, value: bool| *arg == Node::Const { value };
+        match id {
+            Id::And | Id::Or => {
+                // `neutral` is the constant which doesn't change the
+                // result of the function. Its negation decides it.
+                let neutral = id == Id::And;
+                if args.iter().any(|arg| is(arg, !neutral)) {
+                    return Node::Const { value: !neutral };
+                }
+                args.retain(|arg| !is(arg, neutral));
+                if args.len() == 1 {
+                    return args.pop().unwrap();
+                }
+            },
+            Id::Nand if args.iter().any(|arg| is(arg, false)) => {
+                return Node::Const { value: true };
+            },
+            Id::Nor if args.iter().any(|arg| is(arg, true)) => {
+                return Node::Const { value: false };
+            },
+            Id::Xor | Id::Xnor => {
+                // The other argument is the result if one of them
+                // is neutral.
+                let neutral = id == Id::Xnor;
+                if let Some(i) = args.iter().position(|arg| is(arg, neutral)) {
+                    return args.swap_remove(1 - i);
+                }
+            },
+            Id::Imp if is(&args[0], false) || is(&args[1], true) => {
+                return Node::Const { value: true };
+            },
+            Id::Imp if is(&args[0], true) => return args.pop().unwrap(),
+            _ => {},
+        }
+        Node::Func { id, args }
+    }
+}
+
+impl Node {
+    // Apply identities of `Nand` until none of them matches:
+    //   - `Nand(Nand(x, x), Nand(x, x))` is `x`,
+    //   - `Nand(x, Nand(x, x))` is `1`,
+    //   - `Nand(0, x)` is `1` and `Nand(1, x)` is `Nand(x, x)`.
+    // Other functions are kept, but their arguments are simplified.
+    pub fn simplify(&mut self) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            self.rewrite_post_order(|id, args| {
+                match Node::simplify_func(id, &args) {
+                    Some(simpler) => {
+                        changed = true;
+                        simpler
+                    },
+                    None => Node::Func { id, args },
+                }
+            });
+        }
+    }
+
+    // Returns `None` if none of the identities of `simplify`
+    // applies to the function.
+    fn simplify_func(id: Id, args: &[Node]) -> Option<Node> {
+        // `x` if `node` is `Nand(x, x)`, i.e. `Not(x)`.
+        fn negated(node: &Node) -> Option<&Node> {
+            match node {
+                Node::Func { id: Id::Nand, args }
+                    if args.len() == 2 && args[0] == args[1] =>
+                {
+                    Some(&args[0])
+                },
+                _ => None,
+            }
+        }
+
+        let (Id::Nand, [x, y]) = (id, args) else {
+            return None;
+        };
+        match (x, y) {
+            (Node::Const { value: false }, _)
+            | (_, Node::Const { value: false }) => {
+                Some(Node::Const { value: true })
+            },
+            (Node::Const { value: true }, Node::Const { value: true }) => {
+                Some(Node::Const { value: false })
+            },
+            (Node::Const { value: true }, other)
+            | (other, Node::Const { value: true }) => Some(Node::Func {
+                id:   Id::Nand,
+                args: vec![other.clone(), other.clone()],
+            }),
+            _ if x == y => negated(x).cloned(),
+            _ if negated(x) == Some(y) || negated(y) == Some(x) => {
+                Some(Node::Const { value: true })
+            },
+            _ => None,
+        }
+    }
+
+    // Apply laws of boolean algebra to `And`, `Or` and `Not`
+    // until none of them matches:
+    //   - idempotence: `And(a, a)` and `Or(a, a)` are `a`,
+    //   - complementation: `And(a, Not(a))` is `0` and
+    //     `Or(a, Not(a))` is `1`,
+    //   - absorption: `And(a, Or(a, b))` and `Or(a, And(a, b))`
+    //     are `a`,
+    //   - double negation: `Not(Not(a))` is `a`.
+    // This is meant to run before lowering, and together with
+    // `fold` to get rid of the constants it introduces.
+    pub fn simplify_boolean(&mut self) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            self.rewrite_post_order(
+                |id, args| match Node::simplify_boolean_func(id, &args) {
+                    Some(simpler) => {
+                        changed = true;
+                        simpler
+                    },
+                    None => Node::Func { id, args },
+                },
+            );
+        }
+    }
+
+    // Returns `None` if none of the laws of `simplify_boolean`
+    // applies to the function.
+    fn simplify_boolean_func(id: Id, args: &[Node]) -> Option<Node> {
+        let dual = match id {
+            Id::And => Id::Or,
+            Id::Or => Id::And,
+            Id::Not => {
+                return match &args[0] {
+                    Node::Func { id: Id::Not, args } => Some(args[0].clone()),
+                    _ => None,
+                };
+            },
+            _ => return None,
+        };
+        let complemented = args.iter().any(|arg| match arg {
+            Node::Func {
+                id: Id::Not,
+                args: inner,
+            } => args.contains(&inner[0]),
+            _ => false,
+        });
+        if complemented {
+            return Some(Node::Const {
+                value: id == Id::Or,
+            });
+        }
+        let mut kept: Vec<Node> = vec![];
+        for (i, arg) in args.iter().enumerate() {
+            // An argument of the dual function which is also an
+            // argument of this one absorbs the dual function.
+            let absorbed = match arg {
+                Node::Func { id, args: inner } if *id == dual => args
+                    .iter()
+                    .enumerate()
+                    .any(|(j, other)| j != i && inner.contains(other)),
+                _ => false,
+            };
+            if !absorbed && !kept.contains(arg) {
+                kept.push(arg.clone());
+            }
+        }
+        match kept.len() {
+            n if n == args.len() => None,
+            1 => kept.pop(),
+            _ => Some(Node::Func { id, args: kept }),
+        }
+    }
+}
+
+impl Node {
+    // Number of `Nand` functions in the tree. Identical
+    // sub-trees are counted as often as they appear. Use
+    // `Dag::count_gates` to count each of them only once.
+    pub fn count_gates(&self) -> usize {
+        match self {
+            Node::Func { id, args } => {
+                let own = usize::from(*id == Id::Nand);
+                own + args.iter().map(Node::count_gates).sum::<usize>()
+            },
+            Node::Var { .. } | Node::Const { .. } => 0,
+        }
+    }
+
+    // Number of function levels on the longest path from the
+    // root to a leaf. After lowering, this is the number of
+    // gates on the critical path.
+    pub fn depth(&self) -> usize {
+        match self {
+            Node::Func { args, .. } => {
+                1 + args.iter().map(Node::depth).max().unwrap_or(0)
+            },
+            Node::Var { .. } | Node::Const { .. } => 0,
+        }
+    }
+}
+
+// Summary of the shape of a tree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExprStats {
+    // Number of function levels on the longest path from the
+    // root to a leaf.
+    pub depth:      usize,
+    // Number of function nodes of any kind.
+    pub gate_count: usize,
+    // Number of function nodes which are `Nand`s.
+    pub nand_count: usize,
+    // Number of distinct variables.
+    pub var_count:  usize,
+    // Largest number of arguments of any function.
+    pub max_fanin:  usize,
+}
+
+impl Node {
+    // Collect all metrics of `ExprStats` in a single traversal.
+    pub fn stats(&self) -> ExprStats {
+        fn visit<'a>(
+            node: &'a Node,
+            stats: &mut ExprStats,
+            vars: &mut HashSet<&'a str>,
+        ) -> usize {
+            match node {
+                Node::Func { id, args } => {
+                    stats.gate_count += 1;
+                    if *id == Id::Nand {
+                        stats.nand_count += 1;
+                    }
+                    stats.max_fanin = stats.max_fanin.max(args.len());
+                    let depth = args
+                        .iter()
+                        .map(|arg| visit(arg, stats, vars))
+                        .max()
+                        .unwrap_or(0);
+                    depth + 1
+                },
+                Node::Var { id } => {
+                    vars.insert(id);
+                    0
+                },
+                Node::Const { .. } => 0,
+            }
+        }
+
+        let mut stats = ExprStats::default();
+        let mut vars = HashSet::new();
+        stats.depth = visit(self, &mut stats, &mut vars);
+        stats.var_count = vars.len();
+        stats
+    }
+
+    // The metrics which matter most when comparing two circuits
+    // for the same function.
+    pub fn size_metrics(&self) -> SizeMetrics {
+        let stats = self.stats();
+        SizeMetrics {
+            gate_count: stats.gate_count,
+            depth:      stats.depth,
+            var_count:  stats.var_count,
+        }
+    }
+
+    // Whether this tree has fewer functions than `other`. Lower
+    // both first to compare the number of `Nand`s they need.
+    pub fn is_smaller_than(&self, other: &Node) -> bool {
+        self.size_metrics().gate_count < other.size_metrics().gate_count
+    }
+}
+
+// Size of a tree, as returned by `size_metrics`. Metrics are
+// ordered by the number of functions first, then by the depth
+// and then by the number of variables, so the smaller of two
+// metrics belongs to the cheaper circuit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SizeMetrics {
+    // Number of function nodes of any kind.
+    pub gate_count: usize,
+    // Number of function levels on the longest path from the
+    // root to a leaf.
+    pub depth:      usize,
+    // Number of distinct variables.
+    pub var_count:  usize,
+}
+
+// Generates random, but always valid trees for fuzzing. Every
+// function gets exactly as many arguments as `Id::parse` accepts
+// and the nesting depth is bounded, because lowering duplicates
+// sub-trees and would otherwise blow up exponentially.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Node {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>,
+    ) -> arbitrary::Result<Self> {
+        const MAX_DEPTH: usize = 6;
+        const VARS: [&str; 4] = ["a", "b", "c", "d"];
+
+        fn node(
+            u: &mut arbitrary::Unstructured,
+            depth: usize,
+        ) -> arbitrary::Result<Node> {
+            if depth >= MAX_DEPTH || u.ratio(1, 3)? {
+                if u.ratio(1, 8)? {
+                    return Ok(Node::Const {
+                        value: u.arbitrary()?,
+                    });
+                }
+                let id = u.choose(&VARS)?;
+                return Ok(Node::Var {
+                    id: (*id).to_owned(),
+                });
+            }
+
+            let &(_, id, arity) = u.choose(Id::all())?;
+            let num_args = if id.is_variadic() {
+                u.int_in_range(arity..=arity + 2)?
+            } else {
+                arity
+            };
+            let args = (0..num_args)
+                .map(|_| node(u, depth + 1))
+                .collect::<arbitrary::Result<_>>()?;
+            Ok(Node::Func { id, args })
+        }
+
+        node(u, 0)
+    }
+}
+
+impl Node {
+    // Print each argument on its own line, indented one level
+    // deeper than the function, which starts at `indent`.
+    fn fmt_pretty(
+        &self,
+        f: &mut std::fmt::Formatter,
+        indent: usize,
+    ) -> std::fmt::Result {
+        match self {
+            Node::Func { id, args } => {
+                writeln!(f, "{id}(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    write!(f, "{:width$}", "", width = indent + 4)?;
+                    arg.fmt_pretty(f, indent + 4)?;
+                    if i + 1 < args.len() {
+                        write!(f, ",")?;
+                    }
+                    writeln!(f)?;
+                }
+                write!(f, "{:indent$})", "")
+            },
+            _ => write!(f, "{self}"),
+        }
+    }
+}
+
+// `{:#}` prints the tree over multiple lines with indentation.
+impl std::fmt::Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if f.alternate() {
+            return self.fmt_pretty(f, 0);
+        }
         match self {
             Node::Func { id, args } => {
-                for arg in args.iter_mut() {
-                    arg.to_nand();
+                write!(f, "{id}(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{arg}")?;
                 }
+                write!(f, ")")
+            },
+            Node::Var { id } if needs_quotes(id) => write!(f, "{}", quote(id)),
+            Node::Var { id } => write!(f, "{id}"),
+            Node::Const { value } => write!(f, "{}", u8::from(*value)),
+        }
+    }
+}
+
+/// Parse a tree without lowering it, just like `nandu::parse`.
+///
+/// ```
+/// use nandu::{Id, Node};
+///
+/// let tree: Node = "Not(a)".parse().unwrap();
+/// assert_eq!(tree, Node::Func {
+///     id:   Id::Not,
+///     args: vec![Node::Var { id: "a".to_owned() }],
+/// });
+/// ```
+impl std::str::FromStr for Node {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        start(&mut lex(s).peekable())
+    }
+}
+
+// Constants come first, then variables and then functions.
+// Functions are ordered by their id first and by their
+// arguments second.
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        fn rank(node: &Node) -> u8 {
+            match node {
+                Node::Const { .. } => 0,
+                Node::Var { .. } => 1,
+                Node::Func { .. } => 2,
+            }
+        }
+
+        match (self, other) {
+            (Node::Const { value: a }, Node::Const { value: b }) => a.cmp(b),
+            (Node::Var { id: a }, Node::Var { id: b }) => a.cmp(b),
+            (
+                Node::Func {
+                    id: a,
+                    args: a_args,
+                },
+                Node::Func {
+                    id: b,
+                    args: b_args,
+                },
+            ) => a.cmp(b).then_with(|| a_args.cmp(b_args)),
+            _ => rank(self).cmp(&rank(other)),
+        }
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Node {
+    // Sort the arguments of all functions whose result doesn't
+    // depend on the order of their arguments. Afterwards, two
+    // trees which only differ in that order are equal.
+    pub fn canonicalize(&mut self) {
+        self.rewrite_post_order(|id, mut args| {
+            if id != Id::Imp {
+                args.sort();
+            }
+            Node::Func { id, args }
+        });
+    }
+
+    // Rename every variable to what `f` returns for its name,
+    // like adding a prefix to keep the inputs of two circuits
+    // apart.
+    pub fn map_vars(&mut self, f: impl Fn(&str) -> String) {
+        fn visit(node: &mut Node, f: &impl Fn(&str) -> String) {
+            match node {
+                Node::Func { args, .. } => {
+                    for arg in args {
+                        visit(arg, f);
+                    }
+                },
+                Node::Var { id } => *id = f(id),
+                Node::Const { .. } => {},
+            }
+        }
+
+        visit(self, &f);
+    }
+
+    // Sort the arguments of all `Nand`s, so that lowered trees
+    // which only differ in the order of arguments print the same.
+    // Other functions are left as they are.
+    pub fn normalize(&mut self) {
+        self.rewrite_post_order(|id, mut args| {
+            if id == Id::Nand {
+                args.sort();
+            }
+            Node::Func { id, args }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::assignments;
+    use crate::lex::{lex, unspanned, Token};
+    use crate::parse::start;
+    use crate::tests::assert_nand_snapshot;
+
+    #[test]
+    fn and_snapshot() {
+        assert_nand_snapshot("And(a, b)", "Nand(Nand(a, b), Nand(a, b))");
+    }
+
+    #[test]
+    fn or_snapshot() {
+        assert_nand_snapshot("Or(a, b)", "Nand(Nand(a, a), Nand(b, b))");
+    }
+
+    #[test]
+    fn nand_snapshot() {
+        assert_nand_snapshot("Nand(a, b)", "Nand(a, b)");
+    }
+
+    #[test]
+    fn nested_snapshot() {
+        assert_nand_snapshot(
+            "And(a, Or(b, c))",
+            "Nand(Nand(a, Nand(Nand(b, b), Nand(c, c))), \
+             Nand(a, Nand(Nand(b, b), Nand(c, c))))",
+        );
+    }
+
+    fn lowered(input: &str) -> Node {
+        let mut tree = start(&mut lex(input).peekable()).unwrap();
+        tree.to_nand();
+        tree
+    }
+
+    #[test]
+    fn count_gates_of_single_nand() {
+        assert_eq!(lowered("Nand(a, b)").count_gates(), 1);
+    }
+
+    #[test]
+    fn count_gates_of_and() {
+        assert_eq!(lowered("And(a, b)").count_gates(), 3);
+    }
+
+    #[test]
+    fn count_gates_of_nested_functions() {
+        // 3 for the `And`, plus twice the 3 of the `Or`.
+        assert_eq!(lowered("And(a, Or(b, c))").count_gates(), 9);
+        assert_eq!(lowered("Nand(Not(a), Nand(b, c))").count_gates(), 3);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn lowered_and_round_trips_through_json() {
+        let tree = lowered("And(a, b)");
+        let json = serde_json::to_string(&tree).unwrap();
+        assert_eq!(
+            json,
+            r#"{"Func":{"id":"Nand","args":[{"Func":{"id":"Nand","args":"#
+                .to_owned()
+                + r#"[{"Var":{"id":"a"}},{"Var":{"id":"b"}}]}},{"Func":"#
+                + r#"{"id":"Nand","args":[{"Var":{"id":"a"}},"#
+                + r#"{"Var":{"id":"b"}}]}}]}}"#
+        );
+        assert_eq!(serde_json::from_str::<Node>(&json).unwrap(), tree);
+    }
+
+    // Take a tree apart without recursion and return the number
+    // of function levels in it. Dropping a deep tree normally
+    // recurses just like a recursive traversal would.
+    fn depth_and_drop(tree: Node) -> usize {
+        let mut depth = 0;
+        let mut stack = vec![(tree, 0)];
+        while let Some((node, level)) = stack.pop() {
+            if let Node::Func { args, .. } = node {
+                depth = depth.max(level + 1);
+                stack.extend(args.into_iter().map(|arg| (arg, level + 1)));
+            }
+        }
+        depth
+    }
+
+    #[test]
+    fn to_nand_handles_deeply_nested_input() {
+        // Every `And` level doubles the size of the lowered tree, so
+        // a long chain of `And`s can't be lowered in any case. Instead
+        // the chain is made up of `Nand`s, which the lowering keeps,
+        // with an `And` at the bottom which needs to be reached.
+        const DEPTH: usize = 50_000;
+        let mut tree = Node::Func {
+            id:   Id::And,
+            args: vec![Node::Var { id: "a".to_owned() }, Node::Const {
+                value: true,
+            }],
+        };
+        for _ in 0..DEPTH {
+            tree = Node::Func {
+                id:   Id::Nand,
+                args: vec![tree, Node::Var { id: "b".to_owned() }],
+            };
+        }
+        tree.to_nand();
+        assert_eq!(depth_and_drop(tree), DEPTH + 2);
+    }
+
+    #[test]
+    fn balanced_lowering_of_wide_and_is_shallower() {
+        let input = "And(a, b, c, d, e, f, g, h)";
+        let mut folded = input.parse::<Node>().unwrap();
+        folded.to_nand();
+        let mut balanced = input.parse::<Node>().unwrap();
+        balanced.to_nand_balanced();
+        // Each binary `And` is two levels of `Nand`s.
+        assert_eq!(folded.depth(), 2 * 7);
+        assert_eq!(balanced.depth(), 2 * 3);
+        // Every `And` uses its arguments twice, so the shallower tree
+        // duplicates fewer of them, too.
+        assert_eq!(balanced.count_gates(), 63);
+        assert_eq!(folded.count_gates(), 381);
+        let vars = ["a", "b", "c", "d", "e", "f", "g", "h"];
+        for env in crate::eval::assignments(&vars) {
+            assert_eq!(balanced.eval(&env), folded.eval(&env));
+        }
+    }
+
+    #[test]
+    fn balance_variadic_splits_arguments_in_halves() {
+        let balanced = |input: &str| {
+            let mut tree: Node = input.parse().unwrap();
+            tree.balance_variadic();
+            tree.to_string()
+        };
+        assert_eq!(balanced("And(a, b, c, d)"), "And(And(a, b), And(c, d))");
+        assert_eq!(balanced("Or(a, b, c)"), "Or(a, Or(b, c))");
+        assert_eq!(balanced("Xor(a, Or(a, b))"), "Xor(a, Or(a, b))");
+    }
+
+    #[test]
+    fn lower_step_rewrites_one_function_at_a_time() {
+        let mut tree = start(&mut lex("Or(Not(a), b)").peekable()).unwrap();
+        assert!(tree.lower_step());
+        assert_eq!(tree.to_string(), "Or(Nand(a, a), b)");
+        assert!(tree.lower_step());
+        assert_eq!(
+            tree.to_string(),
+            "Nand(Nand(Nand(a, a), Nand(a, a)), Nand(b, b))"
+        );
+        assert!(!tree.lower_step());
+    }
+
+    #[test]
+    fn lower_step_ends_with_to_nand() {
+        for input in [
+            "a",
+            "And(a, b, c)",
+            "Xor(Or(a, b, Not(c)), Imp(a, Xnor(b, 1)))",
+            "Nand(Nor(a, b), And(Nand(a, b), c))",
+        ] {
+            let mut stepped = start(&mut lex(input).peekable()).unwrap();
+            while stepped.lower_step() {}
+            assert_eq!(stepped, lowered(input), "lowering {input}");
+        }
+    }
+
+    #[test]
+    fn to_nand_counted_reports_rewrites() {
+        let counted = |input: &str| {
+            let mut tree: Node = input.parse().unwrap();
+            tree.to_nand_counted()
+        };
+        assert_eq!(counted("Nand(a, Nand(b, c))"), 0);
+        assert_eq!(counted("And(a, b)"), 1);
+        assert_eq!(counted("And(a, Or(b, c))"), 2);
+        assert_eq!(counted("And(a, b, c)"), 2);
+        assert_eq!(counted("a"), 0);
+    }
+
+    #[test]
+    fn to_nand_counted_lowers_like_to_nand() {
+        let mut counted: Node = "Xor(Not(a), Imp(b, c))".parse().unwrap();
+        let mut lowered = counted.clone();
+        assert_eq!(counted.to_nand_counted(), 3);
+        lowered.to_nand();
+        assert_eq!(counted, lowered);
+    }
+
+    #[test]
+    fn to_nand_handles_long_variadic_and() {
+        // Folding turns this into a deep chain of binary `And`s.
+        let mut tree = Node::Func {
+            id:   Id::And,
+            args: vec![Node::Var { id: "a".to_owned() }; 16],
+        };
+        tree.to_nand();
+        assert_eq!(tree.count_gates(), 3 * ((1 << 15) - 1));
+        assert_eq!(depth_and_drop(tree), 2 * 15);
+    }
+
+    // Compare the outputs of both trees for every assignment
+    // of the given variables.
+    #[track_caller]
+    fn assert_same_truth_table(a: &Node, b: &Node, vars: &[&str]) {
+        for env in assignments(vars) {
+            assert_eq!(a.eval(&env), b.eval(&env), "{a} and {b} disagree");
+        }
+    }
+
+    #[test]
+    fn simplify_removes_double_negation() {
+        let mut tree = lowered("Not(Not(a))");
+        tree.simplify();
+        assert_eq!(tree, Node::Var { id: "a".to_owned() });
+    }
+
+    #[test]
+    fn simplify_shrinks_lowered_and() {
+        let tree = lowered("And(a, Not(Not(b)))");
+        let mut simplified = tree.clone();
+        simplified.simplify();
+        assert_eq!(tree.count_gates(), 9);
+        assert_eq!(simplified.count_gates(), 3);
+        assert_eq!(simplified, lowered("And(a, b)"));
+        assert_same_truth_table(&tree, &simplified, &["a", "b"]);
+    }
+
+    #[test]
+    fn simplify_applies_nand_identities() {
+        for (input, expected) in [
+            ("Nand(a, Nand(a, a))", "1"),
+            ("Nand(Nand(b, b), b)", "1"),
+            ("Nand(0, a)", "1"),
+            ("Nand(1, 1)", "0"),
+            ("Nand(a, 1)", "Nand(a, a)"),
+            ("Nand(1, Nand(a, a))", "a"),
+            ("Or(Nand(0, b), c)", "Or(1, c)"),
+        ] {
+            let mut tree = start(&mut lex(input).peekable()).unwrap();
+            let original = tree.clone();
+            tree.simplify();
+            assert_eq!(tree.to_string(), expected, "simplifying {input}");
+            assert_same_truth_table(&original, &tree, &["a", "b", "c"]);
+        }
+    }
+
+    #[test]
+    fn simplify_is_idempotent() {
+        for input in [
+            "Xor(a, Not(b))",
+            "Or(Not(a), Nor(b, Not(c)))",
+            "And(Not(Not(a)), Or(b, 1))",
+        ] {
+            let mut once = lowered(input);
+            once.simplify();
+            let mut twice = once.clone();
+            twice.simplify();
+            assert_eq!(once, twice, "simplifying {input}");
+            assert_same_truth_table(&lowered(input), &once, &["a", "b", "c"]);
+        }
+    }
+
+    fn simplified_boolean(input: &str) -> Node {
+        let original = start(&mut lex(input).peekable()).unwrap();
+        let mut tree = original.clone();
+        tree.simplify_boolean();
+        assert_same_truth_table(&original, &tree, &["a", "b", "c"]);
+        tree
+    }
+
+    #[test]
+    fn simplify_boolean_applies_idempotence() {
+        assert_eq!(simplified_boolean("And(a, a)").to_string(), "a");
+        assert_eq!(simplified_boolean("Or(b, b)").to_string(), "b");
+        assert_eq!(simplified_boolean("And(a, b, a)").to_string(), "And(a, b)");
+    }
+
+    #[test]
+    fn simplify_boolean_applies_complementation() {
+        assert_eq!(simplified_boolean("And(a, Not(a))").to_string(), "0");
+        assert_eq!(simplified_boolean("Or(Not(b), b)").to_string(), "1");
+        assert_eq!(simplified_boolean("And(a, c, Not(c))").to_string(), "0");
+    }
+
+    #[test]
+    fn simplify_boolean_applies_absorption() {
+        assert_eq!(simplified_boolean("And(a, Or(a, b))").to_string(), "a");
+        assert_eq!(simplified_boolean("Or(And(b, a), a)").to_string(), "a");
+        assert_eq!(
+            simplified_boolean("And(a, c, Or(b, c))").to_string(),
+            "And(a, c)"
+        );
+    }
+
+    #[test]
+    fn simplify_boolean_removes_double_negation() {
+        assert_eq!(simplified_boolean("Not(Not(a))").to_string(), "a");
+        assert_eq!(
+            simplified_boolean("Not(Not(Not(a)))").to_string(),
+            "Not(a)"
+        );
+    }
 
-                match id {
-                    Id::And => {
-                        debug_assert_eq!(args.len(), 2);
-                        let nested_1 = Node::Func {
-                            id:   Id::Nand,
-                            args: args.clone(),
-                        };
-                        let nested_2 = Node::Func {
-                            id:   Id::Nand,
-                            args: args.clone(),
-                        };
-                        *self = Node::Func {
-                            id:   Id::Nand,
-                            args: vec![nested_1, nested_2],
-                        };
-                    },
-                    Id::Or => {
-                        debug_assert_eq!(args.len(), 2);
-                        let nested_1 = Node::Func {
-                            id:   Id::Nand,
-                            args: vec![args[0].clone(), args[0].clone()],
-                        };
-                        let nested_2 = Node::Func {
-                            id:   Id::Nand,
-                            args: vec![args[1].clone(), args[1].clone()],
-                        };
-                        *self = Node::Func {
-                            id:   Id::Nand,
-                            args: vec![nested_1, nested_2],
-                        };
-                    },
-                    Id::Nand => {},
-                }
-            },
-            Node::Var { .. } => {},
+    #[test]
+    fn simplify_boolean_keeps_other_functions() {
+        for input in ["Xor(a, a)", "Nand(a, Not(a))", "And(a, b)"] {
+            assert_eq!(simplified_boolean(input).to_string(), input);
         }
     }
-}
 
-impl std::fmt::Display for Node {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            Node::Func { id, args } => {
-                let mut args_str = String::new();
-                for arg in args.iter().take(1) {
-                    args_str.push_str(&arg.to_string());
-                }
-                for arg in args.iter().skip(1) {
-                    args_str.push_str(&format!(", {arg}"));
-                }
+    #[test]
+    fn simplify_boolean_reaches_a_fixpoint() {
+        // The inner `Or` becomes `a` only after its `And`s shrink,
+        // and then the outer `And` is complemented.
+        let tree = simplified_boolean(
+            "And(Or(And(a, a), And(a, b)), Not(Not(Not(a))))",
+        );
+        assert_eq!(tree.to_string(), "0");
+        let input = "Xor(Or(c, And(c, b)), Not(Not(b)))";
+        let mut tree = simplified_boolean(input);
+        assert_eq!(tree.to_string(), "Xor(c, b)");
+        tree.to_nand();
+        assert_eq!(tree.count_gates(), 5);
+        assert_eq!(lowered(input).count_gates(), 41);
+    }
 
-                write!(f, "{id}({args_str})")
-            },
-            Node::Var { id } => write!(f, "{id}"),
-        }
+    #[test]
+    fn depth_before_and_after_lowering() {
+        let tree = start(&mut lex("And(a, Or(b, c))").peekable()).unwrap();
+        assert_eq!(tree.depth(), 2);
+        // The `And` and the `Or` each become two levels of `Nand`s.
+        assert_eq!(lowered("And(a, Or(b, c))").depth(), 4);
+        assert_eq!(Node::Var { id: "a".to_owned() }.depth(), 0);
+        assert_eq!(lowered("Nand(a, b)").depth(), 1);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::lex::Token;
+    #[test]
+    fn pretty_display_indents_arguments() {
+        let tree = lowered("Nand(a, b)");
+        assert_eq!(format!("{tree}"), "Nand(a, b)");
+        assert_eq!(format!("{tree:#}"), "Nand(\n    a,\n    b\n)");
+    }
+
+    #[test]
+    fn pretty_display_of_nested_functions() {
+        let tree = start(&mut lex("Or(Not(a), 1)").peekable()).unwrap();
+        assert_eq!(
+            format!("{tree:#}"),
+            "Or(\n    \
+             Not(\n        \
+             a\n    \
+             ),\n    \
+             1\n\
+             )"
+        );
+    }
 
     #[test]
     fn and_to_nand_works() {
@@ -137,6 +1227,271 @@ mod tests {
         assert_eq!(or_tree, expected_nand_tree);
     }
 
+    #[test]
+    fn variadic_and_is_folded_left() {
+        let mut tree = Node::Func {
+            id:   Id::And,
+            args: ["a", "b", "c", "d"]
+                .map(|id| Node::Var { id: id.to_owned() })
+                .to_vec(),
+        };
+        let expected_tree = Node::Func {
+            id:   Id::And,
+            args: vec![
+                Node::Func {
+                    id:   Id::And,
+                    args: vec![
+                        Node::Func {
+                            id:   Id::And,
+                            args: vec![
+                                Node::Var { id: "a".to_owned() },
+                                Node::Var { id: "b".to_owned() },
+                            ],
+                        },
+                        Node::Var { id: "c".to_owned() },
+                    ],
+                },
+                Node::Var { id: "d".to_owned() },
+            ],
+        };
+        tree.fold_variadic();
+        assert_eq!(tree, expected_tree);
+    }
+
+    #[test]
+    fn variadic_or_is_folded_left() {
+        let mut tree = Node::Func {
+            id:   Id::Or,
+            args: ["a", "b", "c", "d"]
+                .map(|id| Node::Var { id: id.to_owned() })
+                .to_vec(),
+        };
+        let expected_tree = Node::Func {
+            id:   Id::Or,
+            args: vec![
+                Node::Func {
+                    id:   Id::Or,
+                    args: vec![
+                        Node::Func {
+                            id:   Id::Or,
+                            args: vec![
+                                Node::Var { id: "a".to_owned() },
+                                Node::Var { id: "b".to_owned() },
+                            ],
+                        },
+                        Node::Var { id: "c".to_owned() },
+                    ],
+                },
+                Node::Var { id: "d".to_owned() },
+            ],
+        };
+        tree.fold_variadic();
+        assert_eq!(tree, expected_tree);
+    }
+
+    #[test]
+    fn variadic_and_to_nand_works() {
+        assert_nand_snapshot(
+            "And(a, b, c)",
+            "Nand(Nand(Nand(Nand(a, b), Nand(a, b)), c), \
+             Nand(Nand(Nand(a, b), Nand(a, b)), c))",
+        );
+    }
+
+    #[test]
+    fn variadic_or_to_nand_works() {
+        assert_nand_snapshot(
+            "Or(a, b, c)",
+            "Nand(Nand(Nand(Nand(a, a), Nand(b, b)), \
+             Nand(Nand(a, a), Nand(b, b))), Nand(c, c))",
+        );
+    }
+
+    #[test]
+    fn four_argument_and_and_or_lower_like_nested_ones() {
+        let nested_and = crate::translate("And(And(And(a, b), c), d)");
+        assert_eq!(crate::translate("And(a, b, c, d)"), nested_and);
+        let nested_or = crate::translate("Or(Or(Or(a, b), c), d)");
+        assert_eq!(crate::translate("Or(a, b, c, d)"), nested_or);
+    }
+
+    #[test]
+    fn not_to_nand_works() {
+        let mut not_tree = Node::Func {
+            id:   Id::Not,
+            args: vec![Node::Var { id: "a".to_owned() }],
+        };
+        let expected_nand_tree = Node::Func {
+            id:   Id::Nand,
+            args: vec![Node::Var { id: "a".to_owned() }, Node::Var {
+                id: "a".to_owned(),
+            }],
+        };
+        not_tree.to_nand();
+        assert_eq!(not_tree, expected_nand_tree);
+    }
+
+    #[test]
+    fn nested_not_to_nand_works() {
+        assert_nand_snapshot(
+            "Not(Or(a, b))",
+            "Nand(Nand(Nand(a, a), Nand(b, b)), Nand(Nand(a, a), Nand(b, b)))",
+        );
+    }
+
+    #[test]
+    fn xor_to_nand_works() {
+        let mut xor_tree = Node::Func {
+            id:   Id::Xor,
+            args: vec![Node::Var { id: "a".to_owned() }, Node::Var {
+                id: "b".to_owned(),
+            }],
+        };
+        let shared = Node::Func {
+            id:   Id::Nand,
+            args: vec![Node::Var { id: "a".to_owned() }, Node::Var {
+                id: "b".to_owned(),
+            }],
+        };
+        let expected_nand_tree = Node::Func {
+            id:   Id::Nand,
+            args: vec![
+                Node::Func {
+                    id:   Id::Nand,
+                    args: vec![
+                        Node::Var { id: "a".to_owned() },
+                        shared.clone(),
+                    ],
+                },
+                Node::Func {
+                    id:   Id::Nand,
+                    args: vec![Node::Var { id: "b".to_owned() }, shared],
+                },
+            ],
+        };
+        xor_tree.to_nand();
+        assert_eq!(xor_tree, expected_nand_tree);
+    }
+
+    #[test]
+    fn nested_xor_to_nand_works() {
+        assert_nand_snapshot(
+            "Xor(Not(a), b)",
+            "Nand(Nand(Nand(a, a), Nand(Nand(a, a), b)), \
+             Nand(b, Nand(Nand(a, a), b)))",
+        );
+    }
+
+    #[test]
+    fn nor_to_nand_works() {
+        let mut nor_tree = Node::Func {
+            id:   Id::Nor,
+            args: vec![Node::Var { id: "a".to_owned() }, Node::Var {
+                id: "b".to_owned(),
+            }],
+        };
+        let or = Node::Func {
+            id:   Id::Nand,
+            args: vec![
+                Node::Func {
+                    id:   Id::Nand,
+                    args: vec![Node::Var { id: "a".to_owned() }, Node::Var {
+                        id: "a".to_owned(),
+                    }],
+                },
+                Node::Func {
+                    id:   Id::Nand,
+                    args: vec![Node::Var { id: "b".to_owned() }, Node::Var {
+                        id: "b".to_owned(),
+                    }],
+                },
+            ],
+        };
+        let expected_nand_tree = Node::Func {
+            id:   Id::Nand,
+            args: vec![or.clone(), or],
+        };
+        nor_tree.to_nand();
+        assert_eq!(nor_tree, expected_nand_tree);
+    }
+
+    #[test]
+    fn xnor_to_nand_works() {
+        let mut xnor_tree = Node::Func {
+            id:   Id::Xnor,
+            args: vec![Node::Var { id: "a".to_owned() }, Node::Var {
+                id: "b".to_owned(),
+            }],
+        };
+        let mut xor_tree = Node::Func {
+            id:   Id::Xor,
+            args: vec![Node::Var { id: "a".to_owned() }, Node::Var {
+                id: "b".to_owned(),
+            }],
+        };
+        xor_tree.to_nand();
+        let expected_nand_tree = Node::Func {
+            id:   Id::Nand,
+            args: vec![xor_tree.clone(), xor_tree],
+        };
+        let original = xnor_tree.clone();
+        xnor_tree.to_nand();
+        assert_eq!(xnor_tree, expected_nand_tree);
+        for env in assignments(&["a", "b"]) {
+            let expected = env["a"] == env["b"];
+            assert_eq!(original.eval(&env), Ok(expected));
+            assert_eq!(xnor_tree.eval(&env), Ok(expected));
+        }
+    }
+
+    #[test]
+    fn nested_xnor_to_nand_works() {
+        assert_nand_snapshot(
+            "Xnor(Not(a), b)",
+            "Nand(Nand(Nand(Nand(a, a), Nand(Nand(a, a), b)), \
+             Nand(b, Nand(Nand(a, a), b))), \
+             Nand(Nand(Nand(a, a), Nand(Nand(a, a), b)), \
+             Nand(b, Nand(Nand(a, a), b))))",
+        );
+    }
+
+    #[test]
+    fn imp_to_nand_works() {
+        assert_nand_snapshot("Imp(a, b)", "Nand(a, Nand(b, b))");
+        assert_nand_snapshot(
+            "Imp(And(a, b), c)",
+            "Nand(Nand(Nand(a, b), Nand(a, b)), Nand(c, c))",
+        );
+    }
+
+    #[test]
+    fn imp_is_only_false_if_a_is_true_and_b_is_false() {
+        let imp = Node::Func {
+            id:   Id::Imp,
+            args: vec![Node::Var { id: "a".to_owned() }, Node::Var {
+                id: "b".to_owned(),
+            }],
+        };
+        let mut lowered = imp.clone();
+        lowered.to_nand();
+        for env in assignments(&["a", "b"]) {
+            let expected = !(env["a"] && !env["b"]);
+            assert_eq!(imp.eval(&env), Ok(expected));
+            assert_eq!(lowered.eval(&env), Ok(expected));
+        }
+    }
+
+    #[test]
+    fn nested_nor_to_nand_works() {
+        assert_nand_snapshot(
+            "And(Nor(a, b), c)",
+            "Nand(Nand(Nand(Nand(Nand(a, a), Nand(b, b)), \
+             Nand(Nand(a, a), Nand(b, b))), c), \
+             Nand(Nand(Nand(Nand(a, a), Nand(b, b)), \
+             Nand(Nand(a, a), Nand(b, b))), c))",
+        );
+    }
+
     #[test]
     fn generic_tree_to_nand_works() {
         let mut tree = Node::Func {
@@ -201,6 +1556,271 @@ mod tests {
         assert_eq!(tree, expected_nand_tree);
     }
 
+    #[test]
+    fn stats_of_lowered_tree() {
+        let input = "And(a, Or(b, c))";
+        let mut tree = start(&mut lex(input).peekable()).unwrap();
+        tree.to_nand();
+        assert_eq!(tree.stats(), ExprStats {
+            depth:      4,
+            gate_count: 9,
+            nand_count: 9,
+            var_count:  3,
+            max_fanin:  2,
+        });
+    }
+
+    #[test]
+    fn size_metrics_of_and() {
+        let tree = start(&mut lex("And(a, b)").peekable()).unwrap();
+        let metrics = tree.size_metrics();
+        assert_eq!(metrics.gate_count, 1);
+        assert_eq!(metrics.depth, 1);
+        assert_eq!(metrics.var_count, 2);
+        assert_eq!(lowered("And(a, b)").size_metrics(), SizeMetrics {
+            gate_count: 3,
+            depth:      2,
+            var_count:  2,
+        });
+    }
+
+    #[test]
+    fn minimized_and_is_smaller() {
+        let tree = lowered("And(a, Not(Not(b)))");
+        let minimized = tree.minimize().to_tree();
+        assert!(minimized.is_smaller_than(&tree));
+        assert!(!tree.is_smaller_than(&minimized));
+        assert!(minimized.size_metrics() < tree.size_metrics());
+        assert_eq!(
+            minimized.size_metrics(),
+            lowered("And(a, b)").size_metrics()
+        );
+        // The `And` can't be made any smaller.
+        let and = lowered("And(a, b)");
+        assert!(!and.minimize().to_tree().is_smaller_than(&and));
+    }
+
+    #[test]
+    fn constants_are_folded() {
+        fn folded(input: &str) -> String {
+            let mut tree =
+                crate::parse::start(&mut lex(input).peekable()).unwrap();
+            tree.fold();
+            tree.to_string()
+        }
+
+        assert_eq!(folded("And(a, 1)"), "a");
+        assert_eq!(folded("And(a, 0)"), "0");
+        assert_eq!(folded("Or(a, 0)"), "a");
+        assert_eq!(folded("Or(a, 1)"), "1");
+        assert_eq!(folded("Not(0)"), "1");
+        assert_eq!(folded("And(a, 1, b)"), "And(a, b)");
+        assert_eq!(folded("And(1, 1)"), "1");
+        assert_eq!(folded("Or(Not(1), And(b, 1))"), "b");
+        assert_eq!(folded("Nand(a, 0)"), "1");
+        assert_eq!(folded("Nand(1, 1)"), "0");
+        assert_eq!(folded("Nor(1, a)"), "0");
+        assert_eq!(folded("Xor(0, a)"), "a");
+        assert_eq!(folded("Xnor(a, 1)"), "a");
+        assert_eq!(folded("Imp(1, a)"), "a");
+        assert_eq!(folded("Imp(a, 1)"), "1");
+    }
+
+    #[test]
+    fn constants_propagate_through_levels() {
+        fn folded(input: &str) -> Node {
+            let mut tree =
+                crate::parse::start(&mut lex(input).peekable()).unwrap();
+            tree.fold();
+            tree
+        }
+
+        assert_eq!(folded("And(0, And(b, c))").to_string(), "0");
+        assert_eq!(folded("Or(a, Nand(b, Not(1)))").to_string(), "1");
+        assert_eq!(
+            folded("Xor(And(a, Or(b, 1)), Nand(c, And(1, 1)))").to_string(),
+            "Xor(a, Nand(c, 1))"
+        );
+        assert_eq!(
+            folded("Xnor(Imp(1, 0), Nor(Xor(1, 1), And(0, a)))"),
+            Node::Const { value: false }
+        );
+    }
+
+    #[test]
+    fn constants_survive_where_they_cannot_be_folded() {
+        assert_nand_snapshot("Nand(1, 1)", "Nand(1, 1)");
+        assert_nand_snapshot("And(a, 1)", "Nand(Nand(a, 1), Nand(a, 1))");
+
+        let mut tree = Node::Func {
+            id:   Id::Nand,
+            args: vec![Node::Var { id: "a".to_owned() }, Node::Const {
+                value: true,
+            }],
+        };
+        let expected_tree = tree.clone();
+        tree.fold();
+        assert_eq!(tree, expected_tree);
+    }
+
+    #[test]
+    fn from_str_matches_parse() {
+        let input = "Or(And(a, 1), Not(b))";
+        let tree: Node = input.parse().unwrap();
+        assert_eq!(tree, start(&mut lex(input).peekable()).unwrap());
+
+        let input = "And(a, Foo(b))";
+        assert_eq!(input.parse::<Node>(), start(&mut lex(input).peekable()));
+        assert!(input.parse::<Node>().is_err());
+    }
+
+    #[test]
+    fn display_separates_any_number_of_arguments() {
+        let func = |num_args: usize| Node::Func {
+            id:   Id::And,
+            args: ["a", "b", "c"][..num_args]
+                .iter()
+                .map(|id| Node::Var {
+                    id: (*id).to_owned(),
+                })
+                .collect(),
+        };
+        assert_eq!(func(0).to_string(), "And()");
+        assert_eq!(func(1).to_string(), "And(a)");
+        assert_eq!(func(2).to_string(), "And(a, b)");
+        assert_eq!(func(3).to_string(), "And(a, b, c)");
+    }
+
+    #[test]
+    fn display_separates_nested_arguments() {
+        let tree: Node = "Or(And(a, b, c), Not(d), 1)".parse().unwrap();
+        assert_eq!(tree.to_string(), "Or(And(a, b, c), Not(d), 1)");
+    }
+
+    #[test]
+    fn display_is_canonical_for_messy_input() {
+        let tree: Node = "And(  a ,b )".parse().unwrap();
+        assert_eq!(tree.to_string(), "And(a, b)");
+        let tree: Node = "Or(\n\tNot( a),And(b,c ,),\n)".parse().unwrap();
+        assert_eq!(tree.to_string(), "Or(Not(a), And(b, c))");
+    }
+
+    #[test]
+    fn display_quotes_names_which_are_not_plain() {
+        let tree: Node = "And(`my var`, b, `in`, `Clk`)".parse().unwrap();
+        let Node::Func { args, .. } = &tree else {
+            panic!("not a function");
+        };
+        assert_eq!(args[0], Node::Var {
+            id: "my var".to_owned(),
+        });
+        assert_eq!(tree.to_string(), "And(`my var`, b, `in`, `Clk`)");
+        assert_eq!(tree.to_string().parse::<Node>(), Ok(tree));
+        // Plain names don't need quotes, so they lose them.
+        let tree: Node = "Not(`a_1`)".parse().unwrap();
+        assert_eq!(tree.to_string(), "Not(a_1)");
+    }
+
+    #[test]
+    fn display_round_trips_names_with_backticks() {
+        let var = |id: &str| Node::Var { id: id.to_owned() };
+        let tree = Node::Func {
+            id:   Id::Or,
+            args: vec![var("a`b"), var(""), var("c\\d\ne"), var("`")],
+        };
+        assert_eq!(tree.to_string(), r"Or(`a\`b`, ``, `c\\d\ne`, `\``)");
+        assert_eq!(tree.to_string().parse::<Node>(), Ok(tree));
+    }
+
+    #[test]
+    fn map_vars_renames_every_occurrence() {
+        let mut tree: Node = "And(a, Or(a, b))".parse().unwrap();
+        tree.map_vars(|id| {
+            if id == "a" {
+                "x".to_owned()
+            } else {
+                id.to_owned()
+            }
+        });
+        assert_eq!(tree, "And(x, Or(x, b))".parse().unwrap());
+        tree.map_vars(|id| format!("u1_{id}"));
+        assert_eq!(tree.to_string(), "And(u1_x, Or(u1_x, u1_b))");
+    }
+
+    #[test]
+    fn canonicalize_makes_commuted_expressions_equal() {
+        let canonical = |input: &str| {
+            let mut tree: Node = input.parse().unwrap();
+            tree.canonicalize();
+            tree
+        };
+        assert_eq!(canonical("And(a, b)"), canonical("And(b, a)"));
+        assert_eq!(
+            canonical("Or(Xor(1, c), a, Not(b))"),
+            canonical("Or(Not(b), Xor(c, 1), a)")
+        );
+        assert_eq!(
+            canonical("Or(Not(b), Xor(c, 1), a)").to_string(),
+            "Or(a, Not(b), Xor(1, c))"
+        );
+        assert_ne!(canonical("Imp(a, b)"), canonical("Imp(b, a)"));
+    }
+
+    #[test]
+    fn normalize_gives_commuted_nands_the_same_form() {
+        let normalized = |input: &str| {
+            let mut tree: Node = input.parse().unwrap();
+            tree.to_nand();
+            tree.normalize();
+            tree.to_string()
+        };
+        assert_eq!(normalized("Or(Not(b), a)"), normalized("Or(a, Not(b))"));
+        assert_eq!(
+            normalized("And(Xor(a, b), c)"),
+            normalized("And(c, Xor(b, a))")
+        );
+        assert_eq!(normalized("Nand(b, a)"), "Nand(a, b)");
+        assert_ne!(normalized("Imp(a, b)"), normalized("Imp(b, a)"));
+    }
+
+    #[test]
+    fn normalize_keeps_the_truth_table() {
+        let mut tree: Node = "Or(Imp(c, a), Xor(b, Not(a)))".parse().unwrap();
+        tree.to_nand();
+        let mut normalized = tree.clone();
+        normalized.normalize();
+        assert_same_truth_table(&tree, &normalized, &["a", "b", "c"]);
+    }
+
+    #[test]
+    fn hash_is_consistent_with_eq() {
+        fn hash(node: &Node) -> u64 {
+            use std::hash::{Hash, Hasher};
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            node.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let a: Node = "And(a, Or(b, 1))".parse().unwrap();
+        let b: Node = "a & (b | 1)".parse().unwrap();
+        assert_eq!(a, b);
+        assert_eq!(hash(&a), hash(&b));
+
+        let set: HashSet<Node> = [a, b].into_iter().collect();
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn ord_puts_leaves_before_functions() {
+        let mut nodes: Vec<Node> = ["Not(a)", "b", "1", "a", "0", "And(a, b)"]
+            .into_iter()
+            .map(|input| input.parse().unwrap())
+            .collect();
+        nodes.sort();
+        let sorted: Vec<String> = nodes.iter().map(Node::to_string).collect();
+        assert_eq!(sorted, ["0", "1", "a", "b", "And(a, b)", "Not(a)"]);
+    }
+
     #[test]
     fn parse_simple_ast() {
         let mut token_stream = [
@@ -212,6 +1832,7 @@ mod tests {
             Token::RParen,
         ]
         .into_iter()
+        .map(unspanned)
         .peekable();
         let expected_tree = Node::Func {
             id:   Id::And,
@@ -244,6 +1865,7 @@ mod tests {
             Token::RParen,
         ]
         .into_iter()
+        .map(unspanned)
         .peekable();
         let expected_tree = Node::Func {
             id:   Id::And,
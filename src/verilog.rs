@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::names::{is_simple, output_names};
+use crate::netlist::Signal;
+use crate::tree::Node;
+
+// Verilog-2005 keywords, separated by spaces. They can only be
+// used as names if they're escaped.
+const KEYWORDS: &str =
+    "always and assign automatic begin buf bufif0 bufif1 case casex casez \
+    cell cmos config deassign default defparam design disable edge else \
+    end endcase endconfig endfunction endgenerate endmodule endprimitive \
+    endspecify endtable endtask event for force forever fork function \
+    generate genvar highz0 highz1 if ifnone incdir include initial inout \
+    input instance integer join large liblist library localparam \
+    macromodule medium module nand negedge nmos nor noshowcancelled not \
+    notif0 notif1 or output parameter pmos posedge primitive pull0 pull1 \
+    pulldown pullup pulsestyle_ondetect pulsestyle_onevent rcmos real \
+    realtime reg release repeat rnmos rpmos rtran rtranif0 rtranif1 \
+    scalared showcancelled signed small specify specparam strong0 strong1 \
+    supply0 supply1 table task time tran tranif0 tranif1 tri tri0 tri1 \
+    triand trior trireg unsigned use uwire vectored wait wand weak0 weak1 \
+    while wire wor xnor xor";
+
+impl Node {
+    // Emit a Verilog module which computes the expression using
+    // only `nand` primitives, one for each gate of its netlist.
+    // The variables become inputs in the order they first appear
+    // and there is a single output. The expression is lowered
+    // first if it isn't already. Variables whose names aren't
+    // Verilog identifiers, or are keywords, become escaped
+    // identifiers, or are renamed if they can't be escaped.
+    pub fn to_verilog(&self, module_name: &str) -> String {
+        let netlist = self.to_netlist();
+        let vars: Vec<&str> =
+            netlist.inputs().iter().map(String::as_str).collect();
+        // Escaped identifiers can have any printable characters
+        // except whitespace. Wires and gates start with an
+        // underscore, so they can't be confused with variables,
+        // but the output doesn't.
+        let names = output_names(&vars, |id| {
+            id.chars().all(|c| c.is_ascii_graphic())
+                && !(is_simple(id) && id.starts_with('_'))
+        });
+        let mut output = "out".to_owned();
+        while names.values().any(|name| *name == output) {
+            output.push('_');
+        }
+        let names: HashMap<&str, String> = names
+            .into_iter()
+            .map(|(id, name)| (id, verilog_ident(&name)))
+            .collect();
+        let inputs: Vec<&str> =
+            vars.iter().map(|id| names[id].as_str()).collect();
+        let name = |signal: &Signal| match signal {
+            Signal::Gate(id) => format!("_w{}", id.index()),
+            Signal::Input(id) => names[id.as_str()].clone(),
+            Signal::Const(value) => format!("1'b{}", u8::from(*value)),
+        };
+
+        let mut ports = inputs.clone();
+        ports.push(&output);
+        let mut out = String::new();
+        writeln!(out, "module {module_name}({});", ports.join(", ")).unwrap();
+        if !inputs.is_empty() {
+            writeln!(out, "    input {};", inputs.join(", ")).unwrap();
+        }
+        writeln!(out, "    output {output};").unwrap();
+        if !netlist.gates().is_empty() {
+            let wires: Vec<String> = netlist
+                .gate_ids()
+                .map(|id| name(&Signal::Gate(id)))
+                .collect();
+            writeln!(out, "    wire {};", wires.join(", ")).unwrap();
+        }
+        for (i, gate) in netlist.gates().iter().enumerate() {
+            let [a, b] = &gate.inputs;
+            writeln!(out, "    nand _g{i}(_w{i}, {}, {});", name(a), name(b))
+                .unwrap();
+        }
+        writeln!(out, "    assign {output} = {};", name(netlist.output()))
+            .unwrap();
+        out.push_str("endmodule");
+        out
+    }
+}
+
+// `name` as a Verilog identifier. Escaped identifiers start
+// with a backslash and end with whitespace.
+fn verilog_ident(name: &str) -> String {
+    if is_simple(name) && !KEYWORDS.split(' ').any(|keyword| keyword == name) {
+        name.to_owned()
+    } else {
+        format!("\\{name} ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lex::lex;
+    use crate::parse::start;
+    use crate::translate;
+
+    #[test]
+    fn verilog_of_single_nand() {
+        let nand = translate("Nand(a, b)").unwrap();
+        let tree = start(&mut lex(&nand).peekable()).unwrap();
+        assert_eq!(
+            tree.to_verilog("top"),
+            "module top(a, b, out);\n    \
+             input a, b;\n    \
+             output out;\n    \
+             wire _w0;\n    \
+             nand _g0(_w0, a, b);\n    \
+             assign out = _w0;\n\
+             endmodule"
+        );
+    }
+
+    #[test]
+    fn verilog_wires_differ_from_variables() {
+        let tree = start(&mut lex("Nand(w0, g0)").peekable()).unwrap();
+        let verilog = tree.to_verilog("top");
+        assert!(verilog.contains("    nand _g0(_w0, w0, g0);\n"));
+    }
+
+    #[test]
+    fn verilog_lowers_and_names_wires_uniquely() {
+        let tree = start(&mut lex("And(out, Not(1))").peekable()).unwrap();
+        let verilog = tree.to_verilog("top");
+        assert!(verilog.starts_with("module top(out, out_);\n"));
+        assert!(verilog.contains("    wire _w0, _w1, _w2, _w3, _w4;\n"));
+        assert!(verilog.contains("    nand _g0(_w0, 1'b1, 1'b1);\n"));
+        assert!(verilog.contains("    nand _g4(_w4, _w1, _w3);\n"));
+        assert!(verilog.contains("    assign out_ = _w4;\n"));
+    }
+
+    #[test]
+    fn verilog_of_constants_and_variables() {
+        let tree = start(&mut lex("1").peekable()).unwrap();
+        assert_eq!(
+            tree.to_verilog("top"),
+            "module top(out);\n    \
+             output out;\n    \
+             assign out = 1'b1;\n\
+             endmodule"
+        );
+        let tree = start(&mut lex("a").peekable()).unwrap();
+        assert!(tree.to_verilog("top").contains("    assign out = a;\n"));
+    }
+
+    #[test]
+    fn verilog_escapes_keywords() {
+        let tree = start(&mut lex("And(input, wire)").peekable()).unwrap();
+        let verilog = tree.to_verilog("top");
+        assert!(verilog.starts_with("module top(\\input , \\wire , out);\n"));
+        assert!(verilog.contains("    input \\input , \\wire ;\n"));
+        assert!(verilog.contains("    nand _g0(_w0, \\input , \\wire );\n"));
+    }
+
+    #[test]
+    fn verilog_escapes_quoted_variables() {
+        let tree = start(&mut lex("Nand(`a\"b`, `_w0`)").peekable()).unwrap();
+        let verilog = tree.to_verilog("top");
+        assert!(verilog.starts_with("module top(\\a\"b , v_w0, out);\n"));
+        assert!(verilog.contains("    nand _g0(_w0, \\a\"b , v_w0);\n"));
+        let tree = start(&mut lex("Not(`my var`)").peekable()).unwrap();
+        assert!(tree.to_verilog("top").contains("    input my_var;\n"));
+    }
+}
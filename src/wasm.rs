@@ -0,0 +1,23 @@
+use wasm_bindgen::prelude::*;
+
+// Same as `translate`, but callable from JavaScript. Errors
+// are thrown as JavaScript errors with the same message.
+#[wasm_bindgen]
+pub fn translate_wasm(input: &str) -> Result<String, JsValue> {
+    crate::translate(input).map_err(|e| JsError::new(&e.to_string()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Only the successful path can run outside of WebAssembly,
+    // because creating a `JsValue` needs a JavaScript host.
+    #[test]
+    fn translate_wasm_returns_translation() {
+        assert_eq!(
+            translate_wasm("Nand(a, b)").ok(),
+            Some("Nand(a, b)".to_owned())
+        );
+    }
+}
@@ -8,17 +8,46 @@ use predicates::prelude::*;
 
 type DynResult = Result<(), Box<dyn std::error::Error>>;
 
-#[test]
-fn nand_stays_nand_as_cli_arg() -> DynResult {
+// Run the binary with `input` as its argument and require the
+// output to be exactly the `expected` translation. `predicates`
+// prints a diff of the two if they don't match.
+fn assert_nand_snapshot(input: &str, expected: &str) -> DynResult {
     let mut cmd = Command::cargo_bin("nandu")?;
-
-    cmd.arg("Nand(a, b)");
+    cmd.arg(input);
     cmd.assert()
         .success()
-        .stdout(predicate::str::contains("Nand(a, b)"));
+        .stdout(predicate::str::diff(format!("{expected}\n")));
     Ok(())
 }
 
+#[test]
+fn nand_stays_nand_as_cli_arg() -> DynResult {
+    assert_nand_snapshot("Nand(a, b)", "Nand(a, b)")
+}
+
+#[test]
+fn and_snapshot_as_cli_arg() -> DynResult {
+    assert_nand_snapshot("And(a, b)", "Nand(Nand(a, b), Nand(a, b))")
+}
+
+#[test]
+fn or_snapshot_as_cli_arg() -> DynResult {
+    assert_nand_snapshot("Or(a, b)", "Nand(Nand(a, a), Nand(b, b))")
+}
+
+#[test]
+fn not_snapshot_as_cli_arg() -> DynResult {
+    assert_nand_snapshot("Not(a)", "Nand(a, a)")
+}
+
+#[test]
+fn xor_snapshot_as_cli_arg() -> DynResult {
+    assert_nand_snapshot(
+        "Xor(a, b)",
+        "Nand(Nand(a, Nand(a, b)), Nand(b, Nand(a, b)))",
+    )
+}
+
 #[test]
 fn nand_stays_nand_as_pipe() -> DynResult {
     let file = assert_fs::NamedTempFile::new("nand.txt")?;
@@ -32,6 +61,281 @@ fn nand_stays_nand_as_pipe() -> DynResult {
     Ok(())
 }
 
+#[test]
+fn each_line_of_pipe_is_translated() -> DynResult {
+    let file = assert_fs::NamedTempFile::new("two_lines.txt")?;
+    file.write_str("And(a, b)\nNot(c)\n")?;
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.pipe_stdin(file)?;
+    cmd.assert().success().stdout(predicate::str::diff(
+        "Nand(Nand(a, b), Nand(a, b))\nNand(c, c)\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn each_line_of_crlf_pipe_is_translated() -> DynResult {
+    let file = assert_fs::NamedTempFile::new("crlf.txt")?;
+    file.write_str("And(a, b)\r\nNot(c)\r\n")?;
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.pipe_stdin(file)?;
+    cmd.assert().success().stdout(predicate::str::diff(
+        "Nand(Nand(a, b), Nand(a, b))\nNand(c, c)\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn error_after_crlf_names_its_line() -> DynResult {
+    let file = assert_fs::NamedTempFile::new("crlf.txt")?;
+    file.write_str("Not(a)\r\nNot(b)\rNot(c, d\r\n")?;
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.pipe_stdin(file)?;
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("line 3, col 4"));
+    Ok(())
+}
+
+#[test]
+fn cli_target_gates_keeps_allowed_gates() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--target-gates", "and,or", "And(a, Not(b))"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("And(a, Nand(b, b))\n"));
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--target-gates", "and,buf", "a"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::diff("Error: unknown gate 'buf'\n"));
+    Ok(())
+}
+
+#[test]
+fn cli_stats_subcommand() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["stats", "And(a, b)"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("gates: 3\ninputs: 2\ndepth: 2\n"));
+    Ok(())
+}
+
+#[test]
+fn cli_translate_subcommand_and_bare_input_agree() -> DynResult {
+    for args in [&["translate", "And(a, b)"][..], &["And(a, b)"]] {
+        let mut cmd = Command::cargo_bin("nandu")?;
+        cmd.args(args);
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::diff("Nand(Nand(a, b), Nand(a, b))\n"));
+    }
+    Ok(())
+}
+
+#[test]
+fn cli_equiv_subcommand() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["equiv", "Or(a, b)", "Or(b, a)"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("equivalent\n"));
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["equiv", "Or(a, b)"]);
+    cmd.assert().failure().stderr(predicate::str::diff(
+        "Error: missing expression to compare with\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_constants_are_tied_unless_folded() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--format", "verilog", "And(a, 1)"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("nand _g0(_w0, a, 1'b1);"));
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--format", "blif", "And(a, 1)"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(".names _c1\n1\n"));
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--fold", "--format", "verilog", "And(a, 1)"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("1'b").not())
+        .stdout(predicate::str::contains("assign out = a;"));
+    Ok(())
+}
+
+#[test]
+fn cli_assert_equiv_against_file() -> DynResult {
+    let golden = assert_fs::NamedTempFile::new("golden.txt")?;
+    golden.write_str("Nand(Nand(a, b), Nand(a, b))\n")?;
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.arg("assert-equiv").arg("--expected").arg(golden.path());
+    cmd.arg("And(b, a)");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("equivalent\n"));
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.arg("assert-equiv").arg("--expected").arg(golden.path());
+    cmd.arg("Or(a, b)");
+    cmd.assert().failure().stdout(predicate::str::diff(
+        "not equivalent, the input vs. the expected output:\n\
+         a=1, b=0: 1 vs. 0\n\
+         a=0, b=1: 1 vs. 0\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_balanced_lowering_is_shallower() -> DynResult {
+    let input = "And(a, b, c, d)";
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--depth", input]);
+    cmd.assert().success().stdout(predicate::str::diff("6\n"));
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--depth", "--balanced", input]);
+    cmd.assert().success().stdout(predicate::str::diff("4\n"));
+    Ok(())
+}
+
+#[test]
+fn cli_warns_about_constant_output() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--warn-constant", "Or(a, Nand(a, a))"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff(
+            "Nand(Nand(a, a), Nand(Nand(a, a), Nand(a, a)))\n",
+        ))
+        .stderr(predicate::str::diff(
+            "Warning: 'Or(a, Nand(a, a))' is always 1\n",
+        ));
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--warn-constant", "And(a, b)"]);
+    cmd.assert().success().stderr("");
+    Ok(())
+}
+
+#[test]
+fn cli_warn_constant_skips_expressions_with_many_inputs() -> DynResult {
+    let vars: Vec<String> = (0..64).map(|i| format!("v{i}")).collect();
+    let input = format!("And({})", vars.join(", "));
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--warn-constant", "--no-lower", &input]);
+    cmd.assert().success().stderr(predicate::str::diff(format!(
+        "Note: can't tell whether '{input}' is constant: expression has \
+             64 variables, but at most 20 can be checked\n"
+    )));
+    Ok(())
+}
+
+#[test]
+fn cli_inputs_are_sorted() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--inputs", "And(a, Or(c, b))\nNot(1)"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("a, b, c\n\n"));
+    Ok(())
+}
+
+#[test]
+fn cli_diff_prints_paths() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--diff", "And(a, c)", "And(a, b)"]);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::diff("[0, 1]: b vs. c\n[1, 1]: b vs. c\n"));
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--no-lower", "--diff", "a & b", "And(a, b)"]);
+    cmd.assert().success().stdout("");
+    Ok(())
+}
+
+#[test]
+fn cli_shared_format_binds_shared_gates() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--format", "shared", "And(a, b)"]);
+    cmd.assert().success().stdout(predicate::str::diff(
+        "let g0 = Nand(a, b) in Nand(g0, g0)\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_max_gates_passes_within_budget() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--max-gates", "5", "And(a, b)"]);
+    cmd.assert().success().stdout("").stderr("");
+    Ok(())
+}
+
+#[test]
+fn cli_max_gates_fails_over_budget() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--max-gates", "2", "And(a, b)"]);
+    cmd.assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::diff(
+            "Error: circuit has 3 gates, but at most 2 are allowed\n",
+        ));
+    Ok(())
+}
+
+#[test]
+fn cli_repl_keeps_going_after_errors() -> DynResult {
+    let file = assert_fs::NamedTempFile::new("repl.txt")?;
+    file.write_str("Not(a)\nAnd(a, b\n\n# Comment\nFoo(a)\nNand(a, b)\n")?;
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.arg("--repl");
+    cmd.pipe_stdin(file)?;
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("Nand(a, a)\nNand(a, b)\n"))
+        .stderr(predicate::str::diff(
+            "Error at line 2, col 4: unbalanced parentheses: 1 opened, but 0 \
+             closed\n\
+             Error at line 5: unknown function id 'Foo'\n",
+        ));
+    Ok(())
+}
+
+#[test]
+fn cli_repl_splits_lines_at_carriage_returns() -> DynResult {
+    let file = assert_fs::NamedTempFile::new("repl.txt")?;
+    file.write_str("Not(a)\rFoo(a)\r\nNot(b)\n")?;
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.arg("--repl");
+    cmd.pipe_stdin(file)?;
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("Nand(a, a)\nNand(b, b)\n"))
+        .stderr(predicate::str::diff(
+            "Error at line 2: unknown function id 'Foo'\n",
+        ));
+    Ok(())
+}
+
 #[test]
 fn cli_args_overwrite_pipe_inputs() -> DynResult {
     let file = assert_fs::NamedTempFile::new("nand.txt")?;
@@ -54,6 +358,607 @@ fn cli_error_if_trailing_parens() -> DynResult {
     cmd.assert()
         .failure()
         .stderr(predicates::str::contains("Error"))
-        .stderr(predicates::str::contains("')'"));
+        .stderr(predicates::str::contains("unbalanced parentheses"));
+    Ok(())
+}
+
+#[test]
+fn cli_error_reports_position() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.arg("Nand(a, b))");
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("Error at line 1, col 11: "));
+    Ok(())
+}
+
+#[test]
+fn cli_error_points_at_the_token() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.arg("Nand(a, b))");
+    cmd.assert().failure().stderr(predicates::str::starts_with(
+        "Nand(a, b))\n          ^\nError at line 1, col 11: ",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_error_reports_position_on_later_line() -> DynResult {
+    let file = assert_fs::NamedTempFile::new("multi_line.txt")?;
+    file.write_str("And(a,\n  b))")?;
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.pipe_stdin(file)?;
+    cmd.assert().failure().stderr(predicates::str::contains(
+        "Error at line 2, col 5: unbalanced parentheses: 1 opened, but 2 \
+         closed",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_count_prints_number_of_gates() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--count", "And(a, b)"]);
+    cmd.assert().success().stdout(predicate::str::diff("3\n"));
+    Ok(())
+}
+
+#[test]
+fn cli_time_fails_with_untimed_modes() -> DynResult {
+    for other in ["--dump-ast", "--explain", "--annotate"] {
+        let mut cmd = Command::cargo_bin("nandu")?;
+        cmd.args(["--time", other, "And(a, b)"]);
+        cmd.assert()
+            .failure()
+            .stderr(predicates::str::contains(format!(
+                "'--time' can't be used with '{other}'"
+            )));
+    }
+    Ok(())
+}
+
+#[test]
+fn cli_error_on_long_identifiers_names_the_limit() -> DynResult {
+    let input = format!("Not({})", "a".repeat(2000));
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.arg(input);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Error at line 1, col 5: identifier is longer than 1024 bytes\n",
+        ))
+        .stderr(predicates::str::contains("unexpected token").not());
+    Ok(())
+}
+
+#[test]
+fn cli_error_on_unknown_option() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--frobnicate", "And(a, b)"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("unknown option '--frobnicate'"));
+    Ok(())
+}
+
+#[test]
+fn cli_format_dot_prints_digraph() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--format", "dot", "Not(a)"]);
+    cmd.assert().success().stdout(predicate::str::diff(
+        "digraph {\n    \
+         n0 [label=\"Nand\", shape=box];\n    \
+         var_a [label=\"a\", shape=ellipse];\n    \
+         n0 -> var_a;\n    \
+         n0 -> var_a;\n\
+         }\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_python_format() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--format", "python", "Not(a)"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("not (a and a)\n"));
+    Ok(())
+}
+
+#[test]
+fn cli_error_on_unknown_format() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--format", "png", "Not(a)"]);
+    cmd.assert().failure().stderr(predicates::str::contains(
+        "unknown format 'png', expected one of: nand, dot, verilog, blif, \
+         python, shared, json",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_format_verilog_names_modules_apart() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--format", "verilog", "Nand(a, b)\nNot(a)"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("module circuit_0(a, b, out);"))
+        .stdout(predicate::str::contains("module circuit_1(a, out);"));
+    Ok(())
+}
+
+#[test]
+fn cli_format_blif() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["-F", "blif", "Nand(a, b)"]);
+    cmd.assert().success().stdout(predicate::str::diff(
+        ".model circuit\n\
+         .inputs a b\n\
+         .outputs out\n\
+         .names a b _g0\n\
+         0- 1\n\
+         -0 1\n\
+         .names _g0 out\n\
+         1 1\n\
+         .end\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_stats_summarizes_the_circuit() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--stats", "And(a, b)"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("gates: 3\ninputs: 2\ndepth: 2\n"));
+    Ok(())
+}
+
+#[test]
+fn cli_depth_prints_longest_path() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--depth", "And(a, Or(b, c))"]);
+    cmd.assert().success().stdout(predicate::str::diff("4\n"));
+    Ok(())
+}
+
+#[test]
+fn cli_reads_input_from_file() -> DynResult {
+    let file = assert_fs::NamedTempFile::new("input.txt")?;
+    file.write_str("And(a, b)\nNot(a)\n")?;
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.arg("--file").arg(file.path());
+    cmd.assert().success().stdout(predicate::str::diff(
+        "Nand(Nand(a, b), Nand(a, b))\nNand(a, a)\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_reads_several_files_in_order() -> DynResult {
+    let first = assert_fs::NamedTempFile::new("first.txt")?;
+    first.write_str("Not(a)\n")?;
+    let second = assert_fs::NamedTempFile::new("second.txt")?;
+    second.write_str("Nand(a, b)\n")?;
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.arg("--file").arg(first.path());
+    cmd.arg("--file").arg(second.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("Nand(a, a)\nNand(a, b)\n"));
+    Ok(())
+}
+
+#[test]
+fn cli_error_names_the_file() -> DynResult {
+    let good = assert_fs::NamedTempFile::new("good.txt")?;
+    good.write_str("Not(a)\n")?;
+    let bad = assert_fs::NamedTempFile::new("bad.txt")?;
+    bad.write_str("Not(a)\nNand(a, b))\n")?;
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.arg("-f").arg(good.path()).arg("-f").arg(bad.path());
+    cmd.assert()
+        .failure()
+        .stdout("")
+        .stderr(predicates::str::contains(format!(
+            "Error in '{}' at line 2, col 11: ",
+            bad.path().display()
+        )));
+    Ok(())
+}
+
+#[test]
+fn cli_file_takes_precedence_over_argument() -> DynResult {
+    let file = assert_fs::NamedTempFile::new("input.txt")?;
+    file.write_str("Not(a)")?;
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.arg("Nand(a, b)").arg("-f").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("Nand(a, a)\n"));
+    Ok(())
+}
+
+#[test]
+fn cli_writes_output_to_file() -> DynResult {
+    let dir = assert_fs::TempDir::new()?;
+    let output = dir.child("out.v");
+    output.write_str("old contents which are longer than the new ones")?;
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["-F", "dot", "Not(a)", "-o"]).arg(output.path());
+    cmd.assert().success().stdout(predicate::str::is_empty());
+    output.assert(
+        "digraph {\n    \
+         n0 [label=\"Nand\", shape=box];\n    \
+         var_a [label=\"a\", shape=ellipse];\n    \
+         n0 -> var_a;\n    \
+         n0 -> var_a;\n\
+         }\n",
+    );
+    Ok(())
+}
+
+#[test]
+fn cli_error_if_output_cannot_be_created() -> DynResult {
+    let dir = assert_fs::TempDir::new()?;
+    let path = dir.child("missing").child("out.txt");
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.arg("--output").arg(path.path()).arg("Not(a)");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::starts_with(format!(
+            "Error: failed to create '{}': ",
+            path.path().display()
+        )));
+    Ok(())
+}
+
+#[test]
+fn cli_dump_ast_shows_tree_before_and_after_lowering() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--dump-ast", "And(a, b)"]);
+    cmd.assert().success().stdout(predicate::str::diff(
+        "Parsed:\n\
+         Func { id: And, args: [Var { id: \"a\" }, Var { id: \"b\" }] }\n\
+         Lowered:\n\
+         Func { id: Nand, args: [\
+         Func { id: Nand, args: [Var { id: \"a\" }, Var { id: \"b\" }] }, \
+         Func { id: Nand, args: [Var { id: \"a\" }, Var { id: \"b\" }] }\
+         ] }\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_gen_is_deterministic_and_translatable() -> DynResult {
+    let run = |seed: &str| -> Result<String, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("nandu")?;
+        cmd.args(["gen", "--seed", seed, "--depth", "3"]);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        Ok(String::from_utf8(output)?)
+    };
+    let expr = run("42")?;
+    assert_eq!(expr, run("42")?);
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.arg(expr.trim());
+    cmd.assert().success();
+    Ok(())
+}
+
+#[test]
+fn cli_gen_of_deep_trees_can_be_read_again() -> DynResult {
+    for seed in ["4", "5", "6"] {
+        let mut cmd = Command::cargo_bin("nandu")?;
+        cmd.args(["gen", "--seed", seed, "--depth", "1000"]);
+        let output = cmd.assert().success().get_output().stdout.clone();
+
+        let mut cmd = Command::cargo_bin("nandu")?;
+        cmd.arg("--no-lower").arg(String::from_utf8(output)?.trim());
+        cmd.assert().success();
+    }
+    Ok(())
+}
+
+#[test]
+fn cli_gen_rejects_invalid_seed() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["gen", "--seed", "many"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::diff("Error: invalid seed 'many'\n"));
+    Ok(())
+}
+
+#[test]
+fn cli_no_lower_prints_parsed_expression() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--no-lower", "And(a, b)\nOR(a,b,) # Comment\na & ~b"]);
+    cmd.assert().success().stdout(predicate::str::diff(
+        "And(a, b)\nOr(a, b)\nAnd(a, Not(b))\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_no_lower_reformats_or_preserves_layout() -> DynResult {
+    let input = "And(  a ,b ) # Comment";
+    for args in [&["--no-lower"][..], &["--no-lower", "--canonical"]] {
+        let mut cmd = Command::cargo_bin("nandu")?;
+        cmd.args(args).arg(input);
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::diff("And(a, b)\n"));
+    }
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--no-lower", "--preserve", input]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff(format!("{input}\n")));
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--no-lower", "--preserve", "And(  a ,b"]);
+    cmd.assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("unbalanced parentheses"));
+    Ok(())
+}
+
+#[test]
+fn cli_layout_options_need_no_lower() -> DynResult {
+    for layout in ["--preserve", "--canonical"] {
+        let mut cmd = Command::cargo_bin("nandu")?;
+        cmd.args([layout, "And( a,b )"]);
+        cmd.assert()
+            .failure()
+            .stdout("")
+            .stderr(predicate::str::diff(format!(
+                "Error: '{layout}' can only be used with '--no-lower'\n"
+            )));
+    }
+    Ok(())
+}
+
+#[test]
+fn cli_annotate_references_input_spans() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--annotate", "Not(a)\nAnd(a, b)"]);
+    cmd.assert().success().stdout(predicate::str::diff(
+        "Nand(a, a) /* 0..6 */\n\
+         Nand(Nand(a, b) /* 7..16 */, Nand(a, b) /* 7..16 */) /* 7..16 */\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_annotate_repeated_calls_with_their_own_spans() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--annotate", "Not(a)\nNot(a)"]);
+    cmd.assert().success().stdout(predicate::str::diff(
+        "Nand(a, a) /* 0..6 */\nNand(a, a) /* 7..13 */\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_time_reports_phases_on_stderr() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--time", "Not(a)"]);
+    let assert = cmd
+        .assert()
+        .success()
+        .stdout(predicate::str::diff("Nand(a, a)\n"));
+    let stderr = String::from_utf8(assert.get_output().stderr.clone())?;
+    let phases: Vec<&str> = stderr
+        .lines()
+        .map(|line| line.split(':').next().unwrap())
+        .collect();
+    assert_eq!(phases, ["lex", "parse", "lower", "format"]);
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn cli_reads_json_trees() -> DynResult {
+    let json = r#"{"Func": {"id": "And", "args": [
+        {"Var": {"id": "a"}}, {"Var": {"id": "b"}}
+    ]}}"#;
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--format-in", "json", json]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("Nand(Nand(a, b), Nand(a, b))\n"));
+    // Without the option, the `{` gives it away.
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.arg(json);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("Nand(Nand(a, b), Nand(a, b))\n"));
+    Ok(())
+}
+
+#[cfg(not(feature = "serde"))]
+#[test]
+fn cli_json_input_needs_serde() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--format-in", "json", "{}"]);
+    cmd.assert().failure().stderr(predicate::str::diff(
+        "Error: input format 'json' needs the 'serde' feature\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_fanout_of_shared_gates() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--fanout", "And(a, b)"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("a: 1\nb: 1\ngate 0: 2\n"));
+    Ok(())
+}
+
+#[test]
+fn cli_fanout_lowers_trees_which_are_not_lowered() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--target-gates", "and", "--fanout", "And(a, b)"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("a: 1\nb: 1\ngate 0: 2\n"));
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--no-lower", "--fanout", "And(a, b, c)"]);
+    cmd.assert().success().stdout(predicate::str::diff(
+        "a: 1\nb: 1\nc: 1\ngate 0: 2\ngate 1: 1\ngate 2: 2\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_explain_prints_each_step() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--explain", "And(a, Not(b))"]);
+    cmd.assert().success().stdout(predicate::str::diff(
+        "And(a, Not(b))\n\
+         And(a, Nand(b, b))\n\
+         Nand(Nand(a, Nand(b, b)), Nand(a, Nand(b, b)))\n",
+    ));
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--explain", "And(a, b)"]);
+    cmd.assert().success().stdout(predicate::str::diff(
+        "And(a, b)\nNand(Nand(a, b), Nand(a, b))\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_error_on_empty_input() -> DynResult {
+    for input in ["", "  \n", "# Comment"] {
+        let mut cmd = Command::cargo_bin("nandu")?;
+        cmd.arg(input);
+        cmd.assert().failure().stderr(predicate::str::diff(
+            "Error: the input has no expression\n",
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn cli_error_if_file_is_missing() -> DynResult {
+    let dir = assert_fs::TempDir::new()?;
+    let path = dir.child("missing.txt");
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.arg("--file").arg(path.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("Error: failed to read"));
+    Ok(())
+}
+
+#[test]
+fn cli_format_nand_is_the_default() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["-F", "nand", "And(a, b)"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("Nand(Nand(a, b), Nand(a, b))\n"));
+    Ok(())
+}
+
+#[test]
+fn cli_short_format_flag_selects_dot() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["-F", "dot", "Nand(a, b)"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with("digraph {\n"))
+        .stdout(predicate::str::contains("n0 -> var_b;"));
+    Ok(())
+}
+
+#[test]
+fn cli_check_equiv_accepts_translation() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--check-equiv", "Nand(Nand(a, b), Nand(a, b))", "And(a, b)"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("equivalent\n"));
+    Ok(())
+}
+
+#[test]
+fn cli_check_equiv_fails_for_different_functions() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["And(a, b)", "--check-equiv", "Or(a, b)"]);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::diff("not equivalent\n"));
+    Ok(())
+}
+
+#[test]
+fn cli_check_equiv_fails_for_too_many_variables() -> DynResult {
+    let vars: Vec<String> = (0..64).map(|i| format!("v{i}")).collect();
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.arg(format!("And({})", vars.join(", ")));
+    cmd.arg("--check-equiv")
+        .arg(format!("Or({})", vars.join(", ")));
+    cmd.assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::diff(
+        "Error: expression has 64 variables, but at most 20 can be checked\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_check_equiv_strict_requires_the_same_variables() -> DynResult {
+    let args = [
+        "And(a, b)",
+        "--check-equiv",
+        "And(a, Or(b, And(c, Not(c))))",
+    ];
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(args);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("equivalent\n"));
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(args).arg("--strict");
+    cmd.assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::diff(
+            "Error: only one of the expressions uses 'c'\n",
+        ));
+
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--strict", "And(a, b)"]);
+    cmd.assert().failure().stderr(predicate::str::diff(
+        "Error: '--strict' can only be used with '--check-equiv'\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn cli_pretty_prints_indented_arguments() -> DynResult {
+    let mut cmd = Command::cargo_bin("nandu")?;
+    cmd.args(["--pretty", "Not(a)"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("Nand(\n    a,\n    a\n)\n"));
     Ok(())
 }